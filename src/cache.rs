@@ -0,0 +1,246 @@
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+
+use crate::provider::ElevationProvider;
+
+/// Environment variable setting the path of the cache.
+pub const CACHE_ENV: &str = "IGN_ELEVATION_CACHE";
+/// Default maximum number of elevations kept in the cache.
+pub const DEFAULT_MAX_ENTRIES: u64 = 10_000_000;
+
+// Coordinates are stored as integers in units of 1e-7 degree, about 1 cm.
+const COORD_SCALE: f64 = 1e7;
+// Share of the maximum number of entries kept by an eviction, so that the entries are not
+// counted again at every insert once the cache is full.
+const EVICTION_TARGET: f64 = 0.9;
+
+/// Persistent SQLite store of elevations, keyed by dataset and quantised coordinates.
+///
+/// When it holds more than its maximum number of entries, the least recently used ones are
+/// evicted.
+#[derive(Debug)]
+pub struct ElevationCache {
+    conn: Mutex<Connection>,
+    max_entries: Option<u64>,
+    // Number of entries, counted at the first insert and kept up to date by this cache only.
+    entries: Mutex<Option<u64>>,
+}
+
+/// Content of an [`ElevationCache`].
+#[derive(Debug, Clone, Default)]
+pub struct CacheStats {
+    pub entries: u64,
+    /// Number of entries of each dataset.
+    pub datasets: Vec<(String, u64)>,
+    /// Size of the database file in bytes.
+    pub size: u64,
+}
+
+fn quantise(coord: f64) -> i64 {
+    (coord * COORD_SCALE).round() as i64
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+impl ElevationCache {
+    /// Open the cache at `path`, creating it if needed, limited to [`DEFAULT_MAX_ENTRIES`].
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("Failed to open the cache {}", path.display()))?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS elevations (
+                 dataset TEXT NOT NULL,
+                 lon INTEGER NOT NULL,
+                 lat INTEGER NOT NULL,
+                 height REAL NOT NULL,
+                 last_used INTEGER NOT NULL,
+                 PRIMARY KEY (dataset, lon, lat)
+             );
+             CREATE INDEX IF NOT EXISTS elevations_last_used ON elevations (last_used);",
+        )
+        .context("Failed to initialise the cache")?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            max_entries: Some(DEFAULT_MAX_ENTRIES),
+            entries: Mutex::new(None),
+        })
+    }
+
+    /// Keep at most `max_entries` elevations, or no limit with `None`.
+    pub fn with_max_entries(mut self, max_entries: Option<u64>) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Cached elevation of each (longitude, latitude) point of `dataset`, if any.
+    pub fn get(&self, dataset: &str, points: &[(f64, f64)]) -> Result<Vec<Option<f64>>> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut heights = Vec::with_capacity(points.len());
+        {
+            let mut select = tx.prepare_cached(
+                "SELECT height FROM elevations WHERE dataset = ?1 AND lon = ?2 AND lat = ?3",
+            )?;
+            let mut touch = tx.prepare_cached(
+                "UPDATE elevations SET last_used = ?4 WHERE dataset = ?1 AND lon = ?2 AND lat = ?3",
+            )?;
+            let last_used = now();
+            for (lon, lat) in points {
+                let key = params![dataset, quantise(*lon), quantise(*lat)];
+                let height: Option<f64> = select.query_row(key, |row| row.get(0)).optional()?;
+                if height.is_some() {
+                    touch.execute(params![dataset, quantise(*lon), quantise(*lat), last_used])?;
+                }
+                heights.push(height);
+            }
+        }
+        tx.commit()?;
+        Ok(heights)
+    }
+
+    /// Store the elevations of (longitude, latitude) points of `dataset`, except the NaN ones.
+    pub fn insert(&self, dataset: &str, points: &[(f64, f64)], heights: &[f64]) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let mut entries = self.entries.lock().unwrap();
+        let tx = conn.transaction()?;
+        let count = |tx: &Transaction| -> rusqlite::Result<u64> {
+            tx.query_row("SELECT COUNT(*) FROM elevations", [], |row| row.get(0))
+        };
+        let mut count_estimate = match *entries {
+            Some(count) => count,
+            None => count(&tx)?,
+        };
+        {
+            // Updating the existing entries apart tells how many new ones are added.
+            let mut insert = tx.prepare_cached(
+                "INSERT OR IGNORE INTO elevations (dataset, lon, lat, height, last_used)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            let mut update = tx.prepare_cached(
+                "UPDATE elevations SET height = ?4, last_used = ?5
+                 WHERE dataset = ?1 AND lon = ?2 AND lat = ?3",
+            )?;
+            let last_used = now();
+            for ((lon, lat), height) in points.iter().zip(heights) {
+                // The points without data are asked again rather than stored as NULL.
+                if height.is_nan() {
+                    continue;
+                }
+                let row = params![dataset, quantise(*lon), quantise(*lat), height, last_used];
+                if insert.execute(row)? == 1 {
+                    count_estimate += 1;
+                } else {
+                    update.execute(row)?;
+                }
+            }
+        }
+        // Other processes sharing the cache make the estimate drift, hence the exact count
+        // before evicting.
+        if let Some(max_entries) = self.max_entries {
+            if count_estimate > max_entries {
+                count_estimate = count(&tx)?;
+            }
+            if count_estimate > max_entries {
+                let target = (max_entries as f64 * EVICTION_TARGET) as u64;
+                count_estimate -= tx.execute(
+                    "DELETE FROM elevations WHERE rowid IN
+                     (SELECT rowid FROM elevations ORDER BY last_used LIMIT ?1)",
+                    [count_estimate - target],
+                )? as u64;
+            }
+        }
+        tx.commit()?;
+        *entries = Some(count_estimate);
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Result<CacheStats> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement =
+            conn.prepare("SELECT dataset, COUNT(*) FROM elevations GROUP BY dataset")?;
+        let datasets = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, u64>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let size: u64 = conn.query_row(
+            "SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(CacheStats {
+            entries: datasets.iter().map(|(_, n)| n).sum(),
+            datasets,
+            size,
+        })
+    }
+
+    /// Remove every entry, returning how many were removed.
+    pub fn clear(&self) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let removed = conn.execute("DELETE FROM elevations", [])?;
+        conn.execute_batch("VACUUM")?;
+        *self.entries.lock().unwrap() = Some(0);
+        Ok(removed)
+    }
+}
+
+/// [`ElevationProvider`] consulting an [`ElevationCache`] before another provider.
+#[derive(Debug)]
+pub struct CachedProvider<P> {
+    inner: P,
+    cache: ElevationCache,
+    dataset: String,
+}
+
+impl<P: ElevationProvider> CachedProvider<P> {
+    /// Cache the elevations of `inner` under `dataset`, which identifies the data it returns,
+    /// e.g. [`crate::IgnProvider::source`] so that the heights of different endpoints of the
+    /// same dataset are kept apart.
+    pub fn new(inner: P, cache: ElevationCache, dataset: &str) -> Self {
+        Self {
+            inner,
+            cache,
+            dataset: dataset.to_string(),
+        }
+    }
+}
+
+impl<P: ElevationProvider> ElevationProvider for CachedProvider<P> {
+    fn elevations(&self, points: &[(f64, f64)]) -> Result<Vec<f64>> {
+        let cached = self.cache.get(&self.dataset, points)?;
+        let missing: Vec<(f64, f64)> = points
+            .iter()
+            .zip(&cached)
+            .filter(|(_, height)| height.is_none())
+            .map(|(point, _)| *point)
+            .collect();
+        if missing.is_empty() {
+            return Ok(cached.into_iter().flatten().collect());
+        }
+
+        let fetched = self.inner.elevations(&missing)?;
+        anyhow::ensure!(
+            fetched.len() == missing.len(),
+            "Expected {} elevations, got {}",
+            missing.len(),
+            fetched.len()
+        );
+        self.cache.insert(&self.dataset, &missing, &fetched)?;
+        let mut fetched = fetched.into_iter();
+        Ok(cached
+            .into_iter()
+            .map(|height| height.or_else(|| fetched.next()).unwrap_or(f64::NAN))
+            .collect())
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+const HEADER: &str = "ign-heightmap checkpoint v1";
+
+/// On-disk record of the batches already fetched, so that an interrupted download can resume.
+///
+/// The file starts with a header identifying the request, followed by one line per completed
+/// batch: its index and its heights.
+#[derive(Debug)]
+pub struct Checkpoint {
+    path: PathBuf,
+    file: File,
+    completed: BTreeMap<usize, Vec<f64>>,
+}
+
+impl Checkpoint {
+    /// Checkpoint file used for the output `output`.
+    pub fn path_for(output: &str) -> PathBuf {
+        PathBuf::from(format!("{}.checkpoint", output))
+    }
+
+    /// Key identifying a request, so that a checkpoint is never resumed with other parameters.
+    /// `source` identifies the data fetched, e.g. [`crate::IgnProvider::source`], so that the
+    /// batches of another endpoint or dataset are not reused.
+    pub fn key(positions: &[(f64, f64)], batch_size: usize, source: &str) -> String {
+        // FNV-1a, stable across runs and Rust versions.
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let bytes = positions
+            .iter()
+            .flat_map(|(x, y)| [x.to_bits(), y.to_bits()])
+            .flat_map(u64::to_le_bytes)
+            .chain(source.bytes());
+        for byte in bytes {
+            hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+        }
+        format!("{} {} {:016x}", positions.len(), batch_size, hash)
+    }
+
+    /// Start a new checkpoint at `path`, replacing any previous one.
+    pub fn create(path: &Path, key: &str) -> Result<Self> {
+        let mut file = File::create(path)
+            .with_context(|| format!("Failed to create checkpoint {}", path.display()))?;
+        writeln!(file, "{} {}", HEADER, key).context("Failed to write the checkpoint")?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            completed: BTreeMap::new(),
+        })
+    }
+
+    /// Load the checkpoint at `path` to resume a request, or start one if it does not exist.
+    pub fn resume(path: &Path, key: &str) -> Result<Self> {
+        if !path.exists() {
+            return Self::create(path, key);
+        }
+        let reader = BufReader::new(
+            File::open(path)
+                .with_context(|| format!("Failed to open checkpoint {}", path.display()))?,
+        );
+        let mut lines = reader.lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        if header != format!("{} {}", HEADER, key) {
+            anyhow::bail!(
+                "Checkpoint {} was made for another request, remove it or run without --resume",
+                path.display()
+            );
+        }
+
+        // A line cut short by an interruption may have lost heights: the fetch checks the
+        // number of heights of each batch and fetches the incomplete ones again.
+        let mut completed = BTreeMap::new();
+        for line in lines {
+            let line = line?;
+            let mut values = line.split(' ');
+            let index = values.next().and_then(|v| v.parse::<usize>().ok());
+            let heights: Option<Vec<f64>> = values.map(|v| v.parse::<f64>().ok()).collect();
+            if let (Some(index), Some(heights)) = (index, heights) {
+                completed.insert(index, heights);
+            }
+        }
+
+        // Rewrite the file so that new batches are not appended to a truncated line.
+        let mut checkpoint = Self::create(path, key)?;
+        for (index, heights) in &completed {
+            checkpoint.record(*index, heights)?;
+        }
+        Ok(checkpoint)
+    }
+
+    /// Heights of the batches already fetched, by batch index.
+    pub fn completed(&self) -> &BTreeMap<usize, Vec<f64>> {
+        &self.completed
+    }
+
+    /// Persist the heights of a fetched batch.
+    pub fn record(&mut self, index: usize, heights: &[f64]) -> Result<()> {
+        let mut line = index.to_string();
+        for height in heights {
+            line.push(' ');
+            line.push_str(&height.to_string());
+        }
+        writeln!(self.file, "{}", line).context("Failed to write the checkpoint")?;
+        self.file.flush()?;
+        self.completed.insert(index, heights.to_vec());
+        Ok(())
+    }
+
+    /// Delete the checkpoint once the data is saved.
+    pub fn remove(self) -> Result<()> {
+        fs::remove_file(&self.path)
+            .with_context(|| format!("Failed to remove checkpoint {}", self.path.display()))
+    }
+}
@@ -0,0 +1,211 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+
+/// RGBA colour.
+pub type Rgba = [u8; 4];
+
+// Built-in ramps, in the color-relief format of GDAL.
+const TERRAIN: &str = "\
+0% 52 120 60
+15% 98 160 72
+35% 190 196 110
+55% 214 172 104
+75% 158 110 70
+90% 190 180 170
+100% 255 255 255
+";
+const HYPSOMETRIC: &str = "\
+-50 40 90 150
+0 94 152 74
+200 140 186 94
+500 206 212 130
+1000 222 190 128
+1500 196 144 96
+2500 150 104 74
+3500 180 172 166
+4800 255 255 255
+";
+const GRAY: &str = "\
+0% 0 0 0
+100% 255 255 255
+";
+const VIRIDIS: &str = "\
+0% 68 1 84
+25% 59 82 139
+50% 33 145 140
+75% 94 201 98
+100% 253 231 37
+";
+
+/// Names of the built-in ramps.
+pub const BUILTIN_RAMPS: &[&str] = &["terrain", "hypsometric", "gray", "viridis"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Level {
+    Meters(f64),
+    /// Percentage of the range of heights of the map.
+    Percent(f64),
+}
+
+/// Colour ramp mapping heights to colours, interpolated linearly between its stops.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorRamp {
+    stops: Vec<(Level, Rgba)>,
+    nodata: Rgba,
+}
+
+impl ColorRamp {
+    /// The built-in ramp `name`: `terrain` and `viridis` span the heights of the map,
+    /// `hypsometric` uses atlas tints at fixed heights, `gray` goes from black to white.
+    pub fn builtin(name: &str) -> Option<Self> {
+        let text = match name.to_ascii_lowercase().as_str() {
+            "terrain" => TERRAIN,
+            "hypsometric" => HYPSOMETRIC,
+            "gray" | "grey" => GRAY,
+            "viridis" => VIRIDIS,
+            _ => return None,
+        };
+        text.parse().ok()
+    }
+
+    /// The built-in ramp `name`, or else the ramp of the color-relief file at this path.
+    pub fn open(name: &str) -> Result<Self> {
+        if let Some(ramp) = Self::builtin(name) {
+            return Ok(ramp);
+        }
+        if !Path::new(name).exists() {
+            anyhow::bail!(
+                "No colour ramp file '{}', expected a file or one of: {}",
+                name,
+                BUILTIN_RAMPS.join(", ")
+            );
+        }
+        let text =
+            std::fs::read_to_string(name).with_context(|| format!("Failed to read {}", name))?;
+        text.parse()
+            .with_context(|| format!("Invalid colour ramp {}", name))
+    }
+
+    /// Colour of the missing heights, transparent by default.
+    pub fn nodata(&self) -> Rgba {
+        self.nodata
+    }
+
+    /// Colours of `heights`, the percentages of the ramp spanning their range.
+    pub fn colorize(&self, heights: &[f64]) -> Vec<Rgba> {
+        let valid = heights.iter().filter(|h| !h.is_nan());
+        let min = valid.clone().fold(f64::INFINITY, |a, &b| a.min(b));
+        let max = valid.fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+        let mut stops: Vec<(f64, Rgba)> = self
+            .stops
+            .iter()
+            .map(|(level, color)| match level {
+                Level::Meters(meters) => (*meters, *color),
+                Level::Percent(percent) => (min + percent / 100. * (max - min), *color),
+            })
+            .collect();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        heights
+            .iter()
+            .map(|&h| {
+                if h.is_nan() {
+                    self.nodata
+                } else {
+                    interpolate(&stops, h)
+                }
+            })
+            .collect()
+    }
+}
+
+// Colour of `height` between the sorted `stops`, clamped to the extreme ones.
+fn interpolate(stops: &[(f64, Rgba)], height: f64) -> Rgba {
+    let Some(upper) = stops.iter().position(|(level, _)| *level >= height) else {
+        return stops[stops.len() - 1].1;
+    };
+    if upper == 0 {
+        return stops[0].1;
+    }
+    let ((low, low_color), (high, high_color)) = (stops[upper - 1], stops[upper]);
+    let t = if high > low {
+        (height - low) / (high - low)
+    } else {
+        1.
+    };
+    let mut color = [0; 4];
+    for (channel, (a, b)) in color.iter_mut().zip(low_color.iter().zip(&high_color)) {
+        *channel = (*a as f64 + t * (*b as f64 - *a as f64)).round() as u8;
+    }
+    color
+}
+
+impl FromStr for ColorRamp {
+    type Err = anyhow::Error;
+
+    /// Parse the color-relief format of GDAL: one `<height> <R> <G> <B> [<A>]` line per stop,
+    /// the height being in meters, a percentage like `50%` or `nv` for the missing heights.
+    /// Colour names such as `white` may replace the components, and `#` starts a comment.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut stops = Vec::new();
+        let mut nodata = [0, 0, 0, 0];
+        for (number, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line
+                .split(|c: char| c.is_whitespace() || c == ',' || c == ':')
+                .filter(|field| !field.is_empty())
+                .collect();
+            let context = || format!("Invalid colour ramp line {}: '{}'", number + 1, line);
+            let color = parse_color(&fields[1..]).with_context(context)?;
+            let level = fields[0];
+            if level.eq_ignore_ascii_case("nv") {
+                nodata = color;
+            } else if let Some(percent) = level.strip_suffix('%') {
+                stops.push((
+                    Level::Percent(percent.parse().with_context(context)?),
+                    color,
+                ));
+            } else {
+                stops.push((Level::Meters(level.parse().with_context(context)?), color));
+            }
+        }
+        anyhow::ensure!(!stops.is_empty(), "The colour ramp has no stop");
+        Ok(Self { stops, nodata })
+    }
+}
+
+// Colour given by 3 or 4 components, or by name.
+fn parse_color(fields: &[&str]) -> Result<Rgba> {
+    match fields {
+        [name] => named_color(name).with_context(|| format!("Unknown colour '{}'", name)),
+        [r, g, b] => Ok([r.parse()?, g.parse()?, b.parse()?, 255]),
+        [r, g, b, a] => Ok([r.parse()?, g.parse()?, b.parse()?, a.parse()?]),
+        _ => anyhow::bail!("Expected R G B [A] or a colour name"),
+    }
+}
+
+// The colour names understood by GDAL.
+fn named_color(name: &str) -> Option<Rgba> {
+    let color = match name.to_ascii_lowercase().as_str() {
+        "white" => [255, 255, 255, 255],
+        "black" => [0, 0, 0, 255],
+        "red" => [255, 0, 0, 255],
+        "green" => [0, 255, 0, 255],
+        "blue" => [0, 0, 255, 255],
+        "yellow" => [255, 255, 0, 255],
+        "magenta" => [255, 0, 255, 255],
+        "cyan" | "aqua" => [0, 255, 255, 255],
+        "grey" | "gray" => [190, 190, 190, 255],
+        "orange" => [255, 165, 0, 255],
+        "brown" => [165, 42, 42, 255],
+        "purple" | "violet" => [160, 32, 240, 255],
+        "indigo" => [75, 0, 130, 255],
+        "none" => [0, 0, 0, 0],
+        _ => return None,
+    };
+    Some(color)
+}
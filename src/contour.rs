@@ -0,0 +1,317 @@
+//! Contour lines of an elevation grid.
+
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::Result;
+
+use crate::grid::ElevationGrid;
+
+/// Maximum number of contour levels of a map, beyond which the interval is too small.
+pub const MAX_LEVELS: i64 = 10_000;
+
+/// Options of [`contours`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContourOptions {
+    /// Height difference between two contours, in meters.
+    pub interval: f64,
+    /// Height of a contour, the others being at multiples of the interval from it.
+    pub base: f64,
+    /// Mark every `index_every`-th contour from the base as an index contour, never if 0.
+    pub index_every: usize,
+    /// Number of passes of Chaikin's corner cutting smoothing the lines.
+    pub smoothing: usize,
+}
+
+impl Default for ContourOptions {
+    fn default() -> Self {
+        Self {
+            interval: 10.,
+            base: 0.,
+            index_every: 5,
+            smoothing: 0,
+        }
+    }
+}
+
+/// Contour line, in the coordinates of the CRS of its grid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contour {
+    pub elevation: f64,
+    /// Whether it is an index contour, usually drawn thicker and labelled.
+    pub index: bool,
+    /// Points of the line, the last one repeating the first one on a closed line.
+    pub points: Vec<(f64, f64)>,
+}
+
+impl Contour {
+    pub fn is_closed(&self) -> bool {
+        self.points.len() > 2 && self.points.first() == self.points.last()
+    }
+}
+
+// Side of a grid cell where a contour crosses it: the horizontal side from the point (ix, iy)
+// to the east, or the vertical side from it to the north.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Side {
+    Horizontal(usize, usize),
+    Vertical(usize, usize),
+}
+
+/// Contour lines of `data` by marching squares. The cells with a missing height are skipped
+/// and the saddles are resolved with the mean height of the cell.
+///
+/// Fails if the interval is not positive or makes more than [`MAX_LEVELS`] levels.
+pub fn contours(data: &ElevationGrid, options: &ContourOptions) -> Result<Vec<Contour>> {
+    anyhow::ensure!(
+        options.interval > 0. && options.interval.is_finite(),
+        "The contour interval must be positive"
+    );
+    let valid = data.heights.iter().filter(|h| !h.is_nan());
+    let min = valid.clone().fold(f64::INFINITY, |a, &b| a.min(b));
+    let max = valid.fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+    if min >= max {
+        return Ok(Vec::new());
+    }
+
+    let first = ((min - options.base) / options.interval).ceil();
+    let last = ((max - options.base) / options.interval).floor();
+    anyhow::ensure!(
+        last - first < MAX_LEVELS as f64,
+        "A contour interval of {} m makes {} levels from {:.1} m to {:.1} m, more than {}",
+        options.interval,
+        last - first + 1.,
+        min,
+        max,
+        MAX_LEVELS
+    );
+    let (first, last) = (first as i64, last as i64);
+    let mut lines = Vec::new();
+    for k in first..=last {
+        let elevation = options.base + k as f64 * options.interval;
+        let index = options.index_every > 0 && k.rem_euclid(options.index_every as i64) == 0;
+        for side_chain in chain(&segments(data, elevation)) {
+            let mut points: Vec<_> = side_chain
+                .iter()
+                .map(|side| crossing(data, *side, elevation))
+                .collect();
+            for _ in 0..options.smoothing {
+                points = chaikin(&points);
+            }
+            lines.push(Contour {
+                elevation,
+                index,
+                points,
+            });
+        }
+    }
+    Ok(lines)
+}
+
+// Segments of the contour at `elevation`, between the sides of the cells it crosses.
+fn segments(data: &ElevationGrid, elevation: f64) -> Vec<(Side, Side)> {
+    let (nx, ny) = data.grid.shape();
+    let mut segments = Vec::new();
+    for ix in 0..nx.saturating_sub(1) {
+        for iy in 0..ny.saturating_sub(1) {
+            // Corners counterclockwise from the south-west, and the sides following each one.
+            let corners = [
+                data.get(ix, iy),
+                data.get(ix + 1, iy),
+                data.get(ix + 1, iy + 1),
+                data.get(ix, iy + 1),
+            ];
+            if corners.iter().any(|h| h.is_nan()) {
+                continue;
+            }
+            let sides = [
+                Side::Horizontal(ix, iy),
+                Side::Vertical(ix + 1, iy),
+                Side::Horizontal(ix, iy + 1),
+                Side::Vertical(ix, iy),
+            ];
+            let above = corners.map(|h| h >= elevation);
+            let crossed: Vec<Side> = (0..4)
+                .filter(|&i| above[i] != above[(i + 1) % 4])
+                .map(|i| sides[i])
+                .collect();
+            match crossed[..] {
+                [a, b] => segments.push((a, b)),
+                [..] if crossed.len() == 4 => {
+                    // Saddle: cut off the corners on the other side of the center of the cell.
+                    let center = corners.iter().sum::<f64>() / 4. >= elevation;
+                    for i in (0..4).filter(|&i| above[i] != center) {
+                        segments.push((sides[(i + 3) % 4], sides[i]));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    segments
+}
+
+// Join the segments sharing a side into lines.
+fn chain(segments: &[(Side, Side)]) -> Vec<Vec<Side>> {
+    let mut by_side: HashMap<Side, Vec<usize>> = HashMap::new();
+    for (i, (a, b)) in segments.iter().enumerate() {
+        by_side.entry(*a).or_default().push(i);
+        by_side.entry(*b).or_default().push(i);
+    }
+    let mut used = vec![false; segments.len()];
+    // The unused segment at the end of a line, and its other side.
+    let next = |side: Side, used: &mut Vec<bool>| {
+        let i = *by_side[&side].iter().find(|&&i| !used[i])?;
+        used[i] = true;
+        let (a, b) = segments[i];
+        Some(if a == side { b } else { a })
+    };
+
+    let mut lines = Vec::new();
+    for start in 0..segments.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+        let mut line = VecDeque::from([segments[start].0, segments[start].1]);
+        while let Some(side) = next(line[line.len() - 1], &mut used) {
+            line.push_back(side);
+        }
+        if line.front() != line.back() {
+            while let Some(side) = next(line[0], &mut used) {
+                line.push_front(side);
+            }
+        }
+        lines.push(line.into());
+    }
+    lines
+}
+
+// Point where the contour at `elevation` crosses a side, interpolated linearly.
+fn crossing(data: &ElevationGrid, side: Side, elevation: f64) -> (f64, f64) {
+    let (x, y) = (&data.grid.x, &data.grid.y);
+    let ((ix0, iy0), (ix1, iy1)) = match side {
+        Side::Horizontal(ix, iy) => ((ix, iy), (ix + 1, iy)),
+        Side::Vertical(ix, iy) => ((ix, iy), (ix, iy + 1)),
+    };
+    let (z0, z1) = (data.get(ix0, iy0), data.get(ix1, iy1));
+    let t = (elevation - z0) / (z1 - z0);
+    (
+        x[ix0] + t * (x[ix1] - x[ix0]),
+        y[iy0] + t * (y[iy1] - y[iy0]),
+    )
+}
+
+// One pass of Chaikin's corner cutting, keeping the ends of an open line.
+fn chaikin(points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+    let closed = points.first() == points.last();
+    let lerp = |(x0, y0): (f64, f64), (x1, y1): (f64, f64), t: f64| {
+        (x0 + t * (x1 - x0), y0 + t * (y1 - y0))
+    };
+    let mut smoothed = Vec::with_capacity(2 * points.len());
+    if !closed {
+        smoothed.push(points[0]);
+    }
+    for pair in points.windows(2) {
+        smoothed.push(lerp(pair[0], pair[1], 0.25));
+        smoothed.push(lerp(pair[0], pair[1], 0.75));
+    }
+    if closed {
+        smoothed.push(smoothed[0]);
+    } else {
+        smoothed.push(points[points.len() - 1]);
+    }
+    smoothed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Grid;
+    use crate::projection::Crs;
+
+    // Map of nx x ny points 1 m apart from the origin, with the height given by `height(ix, iy)`.
+    fn map(nx: usize, ny: usize, height: impl Fn(usize, usize) -> f64) -> ElevationGrid {
+        let grid = Grid {
+            crs: Crs::lambert93(),
+            center: (3., 46.5),
+            x: (0..nx).map(|ix| ix as f64).collect(),
+            y: (0..ny).map(|iy| iy as f64).collect(),
+            x_resolution: 1.,
+            y_resolution: 1.,
+        };
+        let heights = (0..nx)
+            .flat_map(|ix| (0..ny).map(move |iy| (ix, iy)))
+            .map(|(ix, iy)| height(ix, iy))
+            .collect();
+        ElevationGrid::new(grid, heights)
+    }
+
+    // Single cell with 10 m on the south-west and north-east corners, 0 on the others.
+    fn saddle() -> ElevationGrid {
+        map(2, 2, |ix, iy| if ix == iy { 10. } else { 0. })
+    }
+
+    // Contours at a single elevation, as lines with their ends sorted so they compare as sets.
+    fn lines_at(data: &ElevationGrid, elevation: f64) -> Vec<Vec<(f64, f64)>> {
+        let options = ContourOptions {
+            interval: 100.,
+            base: elevation,
+            ..ContourOptions::default()
+        };
+        let mut lines: Vec<Vec<(f64, f64)>> = contours(data, &options)
+            .unwrap()
+            .into_iter()
+            .map(|contour| {
+                assert_eq!(contour.elevation, elevation);
+                let mut points = contour.points;
+                points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                points
+            })
+            .collect();
+        lines.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        lines
+    }
+
+    #[test]
+    fn saddle_above_center() {
+        // The center is at 5 m, above the contour at 6 m: the high corners are cut off.
+        assert_eq!(
+            lines_at(&saddle(), 6.),
+            [vec![(0., 0.4), (0.4, 0.)], vec![(0.6, 1.), (1., 0.6)]]
+        );
+    }
+
+    #[test]
+    fn saddle_below_center() {
+        // The center is at 5 m, above the contour at 4 m: the low corners are cut off.
+        assert_eq!(
+            lines_at(&saddle(), 4.),
+            [vec![(0., 0.6), (0.4, 1.)], vec![(0.6, 0.), (1., 0.4)]]
+        );
+    }
+
+    #[test]
+    fn saddle_between_hills() {
+        // Two hills meeting diagonally on a saddle cell whose center is at 5 m: above it, a ring
+        // around each hill; below it, a single ring around both.
+        let hills = map(4, 4, |ix, iy| match (ix, iy) {
+            (1, 1) | (2, 2) => 10.,
+            _ => 0.,
+        });
+        let rings = |elevation| {
+            let options = ContourOptions {
+                interval: 100.,
+                base: elevation,
+                ..ContourOptions::default()
+            };
+            let lines = contours(&hills, &options).unwrap();
+            assert!(lines.iter().all(Contour::is_closed));
+            lines.len()
+        };
+        assert_eq!(rings(6.), 2);
+        assert_eq!(rings(4.), 1);
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use anyhow::Result;
+
+use crate::provider::ElevationProvider;
+
+/// Number of points sent to the provider in a single request.
+pub const BATCH_SIZE: usize = 50;
+
+/// How [`fetch_elevations`] splits and sends the requests.
+#[derive(Debug, Clone)]
+pub struct FetchOptions {
+    /// Number of points per request.
+    pub batch_size: usize,
+    /// Maximum number of requests in flight.
+    pub workers: usize,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: BATCH_SIZE,
+            workers: 4,
+        }
+    }
+}
+
+/// A batch of points whose elevation could not be fetched.
+#[derive(Debug)]
+pub struct BatchError {
+    /// Index of the batch, in chunks of [`FetchOptions::batch_size`] positions.
+    pub index: usize,
+    pub error: anyhow::Error,
+}
+
+/// Result of [`fetch_elevations`]: the fetched heights and the batches that failed.
+///
+/// `heights` always has one value per position; the points of failed batches are NaN.
+#[derive(Debug, Default)]
+pub struct Elevations {
+    pub heights: Vec<f64>,
+    pub errors: Vec<BatchError>,
+}
+
+impl Elevations {
+    /// Whether every batch was fetched.
+    pub fn is_complete(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Number of points without data, from failed batches or outside the coverage of the dataset.
+    pub fn missing_points(&self) -> usize {
+        self.heights.iter().filter(|h| h.is_nan()).count()
+    }
+}
+
+// Fetch a single batch, checking that the provider returned one height per point.
+fn fetch_batch<P: ElevationProvider + ?Sized>(
+    provider: &P,
+    batch_pos: &[(f64, f64)],
+) -> Result<Vec<f64>> {
+    let heights = provider.elevations(batch_pos)?;
+    anyhow::ensure!(
+        heights.len() == batch_pos.len(),
+        "Expected {} elevations, got {}",
+        batch_pos.len(),
+        heights.len()
+    );
+    Ok(heights)
+}
+
+/// Fetch the elevation of the (longitude, latitude) `positions` in batches, with up to
+/// `options.workers` requests in flight. Fails only if the options have no batch size or
+/// no workers.
+///
+/// A failed batch is recorded in [`Elevations::errors`] and its points are set to NaN, so that
+/// the heights stay aligned with the positions. `on_batch` is called on the calling thread
+/// with the index and result of each batch as it finishes, e.g. to report progress.
+pub fn fetch_elevations<P: ElevationProvider + Sync + ?Sized>(
+    provider: &P,
+    positions: &[(f64, f64)],
+    options: &FetchOptions,
+    on_batch: impl FnMut(usize, &Result<Vec<f64>>),
+) -> Result<Elevations> {
+    fetch_missing_elevations(provider, positions, options, &BTreeMap::new(), on_batch)
+}
+
+/// Same as [`fetch_elevations`], but only fetch the batches missing from `completed`, which
+/// holds the heights already fetched by batch index, e.g. from a [`crate::Checkpoint`].
+pub fn fetch_missing_elevations<P: ElevationProvider + Sync + ?Sized>(
+    provider: &P,
+    positions: &[(f64, f64)],
+    options: &FetchOptions,
+    completed: &BTreeMap<usize, Vec<f64>>,
+    mut on_batch: impl FnMut(usize, &Result<Vec<f64>>),
+) -> Result<Elevations> {
+    anyhow::ensure!(options.batch_size > 0, "The batch size must be positive");
+    anyhow::ensure!(
+        options.workers > 0,
+        "The number of workers must be positive"
+    );
+    let batches: Vec<&[(f64, f64)]> = positions.chunks(options.batch_size).collect();
+    let mut results: Vec<Option<Result<Vec<f64>>>> = batches
+        .iter()
+        .enumerate()
+        .map(|(index, batch_pos)| {
+            completed
+                .get(&index)
+                .filter(|heights| heights.len() == batch_pos.len())
+                .map(|heights| Ok(heights.clone()))
+        })
+        .collect();
+    let missing: Vec<usize> = (0..batches.len())
+        .filter(|&index| results[index].is_none())
+        .collect();
+
+    // Workers pick the next missing batch from a shared counter and send back the results.
+    let next_batch = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..options.workers.min(missing.len().max(1)) {
+            let sender = sender.clone();
+            let (next_batch, batches, missing) = (&next_batch, &batches, &missing);
+            scope.spawn(move || {
+                while let Some(&index) = missing.get(next_batch.fetch_add(1, Ordering::Relaxed)) {
+                    if sender
+                        .send((index, fetch_batch(provider, batches[index])))
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+        for (index, result) in receiver {
+            on_batch(index, &result);
+            results[index] = Some(result);
+        }
+    });
+
+    let mut elevations = Elevations {
+        heights: Vec::with_capacity(positions.len()),
+        errors: Vec::new(),
+    };
+    for (index, (batch_pos, result)) in batches.iter().zip(results).enumerate() {
+        match result.expect("every batch is fetched") {
+            Ok(heights) => elevations.heights.extend(heights),
+            Err(error) => {
+                elevations.errors.push(BatchError { index, error });
+                elevations
+                    .heights
+                    .extend(std::iter::repeat_n(f64::NAN, batch_pos.len()));
+            }
+        }
+    }
+    Ok(elevations)
+}
@@ -0,0 +1,408 @@
+use std::f64::consts::PI;
+use std::str::FromStr;
+
+use anyhow::Context;
+
+use crate::projection::Crs;
+
+pub const METERS_PER_LAT_DEGREE: f64 = 111000.;
+/// Mean radius of the earth, in meters.
+pub const EARTH_RADIUS: f64 = 6_371_000.;
+
+/// Regular grid of map points, stored as its `x` and `y` axes in the coordinates of `crs`:
+/// longitude and latitude for [`Crs::Wgs84`], meters for a projected system.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grid {
+    pub crs: Crs,
+    /// Requested (longitude, latitude) center of the grid.
+    pub center: (f64, f64),
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    /// Distance in meters between two points along x.
+    pub x_resolution: f64,
+    /// Distance in meters between two points along y.
+    pub y_resolution: f64,
+}
+
+/// Area given by its extreme longitudes and latitudes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+/// Geographic point, such as an observer or a pour point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatLon {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl FromStr for LatLon {
+    type Err = anyhow::Error;
+
+    /// Parse `latitude,longitude`.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .context("Invalid coordinate")?;
+        let [latitude, longitude] = values[..] else {
+            anyhow::bail!("Expected latitude,longitude");
+        };
+        Ok(Self {
+            latitude,
+            longitude,
+        })
+    }
+}
+
+impl std::fmt::Display for LatLon {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.latitude, self.longitude)
+    }
+}
+
+impl FromStr for BoundingBox {
+    type Err = anyhow::Error;
+
+    /// Parse `min_lon,min_lat,max_lon,max_lat`.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .context("Invalid coordinate in the bounding box")?;
+        let [min_lon, min_lat, max_lon, max_lat] = values[..] else {
+            anyhow::bail!("Expected min_lon,min_lat,max_lon,max_lat");
+        };
+        anyhow::ensure!(
+            min_lon < max_lon && min_lat < max_lat,
+            "The minimum coordinates must be below the maximum ones"
+        );
+        Ok(Self {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+        })
+    }
+}
+
+impl Grid {
+    /// Build the square grid of size `size` meters centered on the given coordinate.
+    pub fn new(latitude: f64, longitude: f64, size: f64, resolution: f64) -> Self {
+        Self::rectangle(latitude, longitude, size, size, resolution, resolution)
+    }
+
+    /// Build the grid of `width` x `height` meters centered on the given coordinate.
+    ///
+    /// The grid is in longitude/latitude, with meters converted to degrees at the center.
+    pub fn rectangle(
+        latitude: f64,
+        longitude: f64,
+        width: f64,
+        height: f64,
+        x_resolution: f64,
+        y_resolution: f64,
+    ) -> Self {
+        Self::with_crs(
+            Crs::Wgs84,
+            latitude,
+            longitude,
+            width,
+            height,
+            x_resolution,
+            y_resolution,
+        )
+    }
+
+    /// Build the grid of `width` x `height` meters centered on the given coordinate, laid out
+    /// in the coordinates of `crs`.
+    pub fn with_crs(
+        crs: Crs,
+        latitude: f64,
+        longitude: f64,
+        width: f64,
+        height: f64,
+        x_resolution: f64,
+        y_resolution: f64,
+    ) -> Self {
+        let (x, y) = match crs {
+            Crs::Wgs84 => (
+                calculate_axis(
+                    longitude,
+                    width,
+                    x_resolution,
+                    meters_per_lon_degree(latitude),
+                ),
+                calculate_axis(latitude, height, y_resolution, METERS_PER_LAT_DEGREE),
+            ),
+            Crs::Projected { .. } => {
+                let (x, y) = crs.from_wgs84(longitude, latitude);
+                (
+                    calculate_axis(x, width, x_resolution, 1.),
+                    calculate_axis(y, height, y_resolution, 1.),
+                )
+            }
+        };
+        Self {
+            crs,
+            center: (longitude, latitude),
+            x,
+            y,
+            x_resolution,
+            y_resolution,
+        }
+    }
+
+    /// Build the grid covering a bounding box.
+    pub fn from_bbox(bbox: &BoundingBox, x_resolution: f64, y_resolution: f64) -> Self {
+        Self::from_bbox_with_crs(Crs::Wgs84, bbox, x_resolution, y_resolution)
+    }
+
+    /// Build the grid covering a bounding box, laid out in the coordinates of `crs`.
+    pub fn from_bbox_with_crs(
+        crs: Crs,
+        bbox: &BoundingBox,
+        x_resolution: f64,
+        y_resolution: f64,
+    ) -> Self {
+        let latitude = 0.5 * (bbox.min_lat + bbox.max_lat);
+        let longitude = 0.5 * (bbox.min_lon + bbox.max_lon);
+        let (width, height) = match crs {
+            Crs::Wgs84 => (
+                (bbox.max_lon - bbox.min_lon) * meters_per_lon_degree(latitude),
+                (bbox.max_lat - bbox.min_lat) * METERS_PER_LAT_DEGREE,
+            ),
+            Crs::Projected { .. } => {
+                // Extent of the projected corners of the box, around its projected center.
+                let (cx, cy) = crs.from_wgs84(longitude, latitude);
+                let corners = [
+                    (bbox.min_lon, bbox.min_lat),
+                    (bbox.min_lon, bbox.max_lat),
+                    (bbox.max_lon, bbox.min_lat),
+                    (bbox.max_lon, bbox.max_lat),
+                ];
+                corners.iter().fold((0., 0.), |(w, h), (lon, lat)| {
+                    let (x, y) = crs.from_wgs84(*lon, *lat);
+                    (
+                        f64::max(w, 2. * (x - cx).abs()),
+                        f64::max(h, 2. * (y - cy).abs()),
+                    )
+                })
+            }
+        };
+        Self::with_crs(
+            crs,
+            latitude,
+            longitude,
+            width,
+            height,
+            x_resolution,
+            y_resolution,
+        )
+    }
+
+    /// Build the grid of `nx` x `ny` points `spacing` apart in the coordinates of `crs`, from
+    /// the south-west point `origin`, such as the grid of a raster read back.
+    pub fn from_origin(
+        crs: Crs,
+        origin: (f64, f64),
+        spacing: (f64, f64),
+        nx: usize,
+        ny: usize,
+    ) -> Self {
+        let ((x0, y0), (dx, dy)) = (origin, spacing);
+        let x: Vec<f64> = (0..nx).map(|ix| x0 + ix as f64 * dx).collect();
+        let y: Vec<f64> = (0..ny).map(|iy| y0 + iy as f64 * dy).collect();
+        let middle =
+            |start: f64, step: f64, len: usize| start + 0.5 * len.saturating_sub(1) as f64 * step;
+        let center = crs.to_wgs84(middle(x0, dx, nx), middle(y0, dy, ny));
+        let (x_resolution, y_resolution) = match crs {
+            Crs::Wgs84 => (
+                dx * meters_per_lon_degree(center.1),
+                dy * METERS_PER_LAT_DEGREE,
+            ),
+            Crs::Projected { .. } => (dx, dy),
+        };
+        Self {
+            crs,
+            center,
+            x,
+            y,
+            x_resolution,
+            y_resolution,
+        }
+    }
+
+    /// Number of points along x and along y.
+    pub fn shape(&self) -> (usize, usize) {
+        (self.x.len(), self.y.len())
+    }
+
+    /// Distance between two points along x and along y, in the coordinates of the CRS.
+    pub fn spacing(&self) -> (f64, f64) {
+        let step = |axis: &[f64], resolution: f64, meters_per_unit: f64| match axis {
+            [first, second, ..] => second - first,
+            _ => resolution / meters_per_unit,
+        };
+        match self.crs {
+            Crs::Wgs84 => {
+                let latitude = self.y.first().copied().unwrap_or_default();
+                (
+                    step(&self.x, self.x_resolution, meters_per_lon_degree(latitude)),
+                    step(&self.y, self.y_resolution, METERS_PER_LAT_DEGREE),
+                )
+            }
+            Crs::Projected { .. } => (
+                step(&self.x, self.x_resolution, 1.),
+                step(&self.y, self.y_resolution, 1.),
+            ),
+        }
+    }
+
+    /// Size in meters of the cells of the row `iy` along x and along y. The cells of a
+    /// longitude/latitude grid narrow towards the poles.
+    pub fn cell_size(&self, iy: usize) -> (f64, f64) {
+        let (dx, dy) = self.spacing();
+        match self.crs {
+            Crs::Wgs84 => {
+                let latitude = self.y.get(iy).copied().unwrap_or_default();
+                (
+                    dx * meters_per_lon_degree(latitude),
+                    dy * METERS_PER_LAT_DEGREE,
+                )
+            }
+            Crs::Projected { .. } => (dx, dy),
+        }
+    }
+
+    /// All the points of the grid in its own coordinates, x-major.
+    pub fn coordinates(&self) -> Vec<(f64, f64)> {
+        self.x
+            .iter()
+            .flat_map(|x| self.y.iter().map(|y| (*x, *y)))
+            .collect()
+    }
+
+    /// Indices (ix, iy) of the point of the grid nearest to `point`, or `None` outside the grid.
+    pub fn nearest_point(&self, point: &LatLon) -> Option<(usize, usize)> {
+        let (x, y) = self.crs.from_wgs84(point.longitude, point.latitude);
+        let (dx, dy) = self.spacing();
+        let (ix, iy) = (
+            ((x - self.x.first()?) / dx).round(),
+            ((y - self.y.first()?) / dy).round(),
+        );
+        let (nx, ny) = self.shape();
+        (ix >= 0. && iy >= 0. && (ix as usize) < nx && (iy as usize) < ny)
+            .then_some((ix as usize, iy as usize))
+    }
+
+    /// All the (longitude, latitude) points of the grid, x-major.
+    pub fn positions(&self) -> Vec<(f64, f64)> {
+        self.coordinates()
+            .into_iter()
+            .map(|(x, y)| self.crs.to_wgs84(x, y))
+            .collect()
+    }
+}
+
+/// Physical quantity of the values of an [`ElevationGrid`], as named by the outputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quantity {
+    /// Name of the dataset or variable.
+    pub name: &'static str,
+    pub long_name: &'static str,
+    pub units: &'static str,
+    /// CF standard name, if any.
+    pub standard_name: Option<&'static str>,
+}
+
+impl Quantity {
+    pub const ELEVATION: Quantity = Quantity {
+        name: "elevation",
+        long_name: "elevation above sea level",
+        units: "m",
+        standard_name: Some("surface_altitude"),
+    };
+}
+
+/// Elevations fetched on a [`Grid`], in the same order as [`Grid::positions`], or another
+/// quantity derived from them such as a slope.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElevationGrid {
+    pub grid: Grid,
+    pub heights: Vec<f64>,
+    /// Elevation dataset the heights come from, if known.
+    pub source: Option<String>,
+    pub quantity: Quantity,
+}
+
+impl ElevationGrid {
+    pub fn new(grid: Grid, heights: Vec<f64>) -> Self {
+        Self {
+            grid,
+            heights,
+            source: None,
+            quantity: Quantity::ELEVATION,
+        }
+    }
+
+    /// Values of another quantity on the same grid, from the same source.
+    pub fn derive(&self, values: Vec<f64>, quantity: Quantity) -> Self {
+        Self {
+            grid: self.grid.clone(),
+            heights: values,
+            source: self.source.clone(),
+            quantity,
+        }
+    }
+
+    /// Record the elevation dataset the heights come from.
+    pub fn with_source(mut self, source: &str) -> Self {
+        self.source = Some(source.to_string());
+        self
+    }
+
+    /// Height of the point at index `ix` along x and `iy` along y.
+    pub fn get(&self, ix: usize, iy: usize) -> f64 {
+        self.heights[ix * self.grid.y.len() + iy]
+    }
+
+    /// The heights as rows from north to south, each from west to east, as in a north-up raster.
+    pub fn rows_north_up(&self) -> Vec<f64> {
+        let (nx, ny) = self.grid.shape();
+        (0..ny)
+            .rev()
+            .flat_map(|iy| (0..nx).map(move |ix| self.get(ix, iy)))
+            .collect()
+    }
+}
+
+fn meters_per_lon_degree(latitude: f64) -> f64 {
+    METERS_PER_LAT_DEGREE * (2. * PI * latitude / 360.).cos()
+}
+
+// Positions along one axis of the points spaced by 'resolution' meters over 'extent' meters
+// centered on 'center', in units of 'meters_per_degree' meters.
+fn calculate_axis(center: f64, extent: f64, resolution: f64, meters_per_degree: f64) -> Vec<f64> {
+    let map_size = f64::floor(extent / resolution) as i64;
+    (0..map_size)
+        .map(|i| center - (0.5 * extent - (i as f64) * resolution) / meters_per_degree)
+        .collect()
+}
+
+// Calculate the x and y positions of the map points as lattitude/longitude.
+pub fn calculate_xy_positions(
+    latitude: f64,
+    longitude: f64,
+    size: f64,
+    resolution: f64,
+) -> (Vec<f64>, Vec<f64>) {
+    let grid = Grid::new(latitude, longitude, size, resolution);
+    (grid.x, grid.y)
+}
@@ -0,0 +1,673 @@
+//! Surface flow of the water over an elevation grid.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, VecDeque};
+use std::str::FromStr;
+
+use anyhow::Result;
+
+use crate::grid::{ElevationGrid, Quantity};
+
+/// Height added between two successive cells of a filled depression or flat, in meters, so
+/// that the water flows across it.
+pub const FLAT_INCREMENT: f64 = 1e-5;
+
+// Offsets of the 8 neighbours clockwise from the east, with their D8 code as used by ESRI.
+const NEIGHBOURS: [(i64, i64, u8); 8] = [
+    (1, 0, 1),
+    (1, -1, 2),
+    (0, -1, 4),
+    (-1, -1, 8),
+    (-1, 0, 16),
+    (-1, 1, 32),
+    (0, 1, 64),
+    (1, 1, 128),
+];
+
+/// Method of routing the flow out of a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlowMethod {
+    /// All the flow to the steepest of the 8 neighbours.
+    #[default]
+    D8,
+    /// Flow in the direction of steepest descent on the 8 triangular facets around the cell,
+    /// split between the two neighbours on either side of it (Tarboton, 1997).
+    DInfinity,
+}
+
+impl FromStr for FlowMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "d8" => Ok(FlowMethod::D8),
+            "dinf" | "d-infinity" | "dinfinity" => Ok(FlowMethod::DInfinity),
+            _ => anyhow::bail!("Unknown flow method '{}', expected d8 or dinf", s),
+        }
+    }
+}
+
+/// Polygon of grid cells, in the coordinates of the CRS of its grid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    /// Outer ring, counterclockwise, its last point repeating the first one.
+    pub exterior: Vec<(f64, f64)>,
+    /// Inner rings, clockwise.
+    pub holes: Vec<Vec<(f64, f64)>>,
+    /// Area in square meters.
+    pub area: f64,
+}
+
+// Cell of the priority queue of the flood, lowest first.
+#[derive(PartialEq)]
+struct Cell(f64, usize, usize);
+
+impl Eq for Cell {}
+
+impl PartialOrd for Cell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Cell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.total_cmp(&self.0)
+    }
+}
+
+// Neighbour of (ix, iy) at the given offset, if inside the grid.
+fn neighbour(
+    data: &ElevationGrid,
+    ix: usize,
+    iy: usize,
+    (ox, oy): (i64, i64),
+) -> Option<(usize, usize)> {
+    let (nx, ny) = data.grid.shape();
+    let (x, y) = (ix as i64 + ox, iy as i64 + oy);
+    (x >= 0 && y >= 0 && (x as usize) < nx && (y as usize) < ny).then_some((x as usize, y as usize))
+}
+
+/// Heights of `data` with the depressions filled by the priority flood of Barnes et al. (2014),
+/// so that every cell drains to the edge of the map or to a missing height. The filled areas
+/// slope by [`FLAT_INCREMENT`] towards their outlet.
+pub fn fill_depressions(data: &ElevationGrid) -> ElevationGrid {
+    let (nx, ny) = data.grid.shape();
+    let mut filled = data.heights.clone();
+    let mut closed: Vec<bool> = filled.iter().map(|h| h.is_nan()).collect();
+    let mut open = BinaryHeap::new();
+    let mut pits = VecDeque::new();
+
+    // The flood starts from the cells on the edges of the map and next to missing heights.
+    for ix in 0..nx {
+        for iy in 0..ny {
+            let height = data.get(ix, iy);
+            let outlet = NEIGHBOURS.iter().any(|&(ox, oy, _)| {
+                neighbour(data, ix, iy, (ox, oy)).is_none_or(|(x, y)| data.get(x, y).is_nan())
+            });
+            if !height.is_nan() && outlet {
+                closed[ix * ny + iy] = true;
+                open.push(Cell(height, ix, iy));
+            }
+        }
+    }
+    while let Some(Cell(height, ix, iy)) = pits.pop_front().or_else(|| open.pop()) {
+        for &(ox, oy, _) in &NEIGHBOURS {
+            let Some((x, y)) = neighbour(data, ix, iy, (ox, oy)) else {
+                continue;
+            };
+            let index = x * ny + y;
+            if closed[index] {
+                continue;
+            }
+            closed[index] = true;
+            if filled[index] <= height + FLAT_INCREMENT {
+                filled[index] = height + FLAT_INCREMENT;
+                pits.push_back(Cell(filled[index], x, y));
+            } else {
+                open.push(Cell(filled[index], x, y));
+            }
+        }
+    }
+    data.derive(filled, data.quantity)
+}
+
+// Distance in meters to the neighbour at the given offset.
+fn distance(data: &ElevationGrid, iy: usize, (ox, oy): (i64, i64)) -> f64 {
+    let (dx, dy) = data.grid.cell_size(iy);
+    (ox as f64 * dx).hypot(oy as f64 * dy)
+}
+
+// Index in NEIGHBOURS of the steepest downslope neighbour of (ix, iy), if any.
+fn d8(data: &ElevationGrid, ix: usize, iy: usize) -> Option<usize> {
+    let height = data.get(ix, iy);
+    let mut steepest = None;
+    let mut max_slope = 0.;
+    for (k, &(ox, oy, _)) in NEIGHBOURS.iter().enumerate() {
+        let Some((x, y)) = neighbour(data, ix, iy, (ox, oy)) else {
+            continue;
+        };
+        let slope = (height - data.get(x, y)) / distance(data, iy, (ox, oy));
+        if slope > max_slope {
+            (steepest, max_slope) = (Some(k), slope);
+        }
+    }
+    steepest
+}
+
+// Neighbours receiving the flow out of a cell, with their share of it.
+type Receivers = [((usize, usize), f64); 2];
+
+// Direction of the flow out of (ix, iy) by the D-infinity method, in degrees clockwise from
+// north, with the neighbours receiving it and their share of the flow.
+fn d_infinity(data: &ElevationGrid, ix: usize, iy: usize) -> Option<(f64, Receivers)> {
+    let height = data.get(ix, iy);
+    let (dx, dy) = data.grid.cell_size(iy);
+    let mut best = None;
+    let mut max_slope = 0.;
+    // Facets between a side neighbour and a corner neighbour, clockwise from the east.
+    let facets = [0, 2, 4, 6]
+        .into_iter()
+        .flat_map(|side| [(side, (side + 7) % 8), (side, side + 1)]);
+    for (side, corner) in facets {
+        let (side, corner) = (NEIGHBOURS[side], NEIGHBOURS[corner]);
+        let (Some(side_cell), Some(corner_cell)) = (
+            neighbour(data, ix, iy, (side.0, side.1)),
+            neighbour(data, ix, iy, (corner.0, corner.1)),
+        ) else {
+            continue;
+        };
+        let (side_height, corner_height) = (
+            data.get(side_cell.0, side_cell.1),
+            data.get(corner_cell.0, corner_cell.1),
+        );
+        if side_height.is_nan() || corner_height.is_nan() {
+            continue;
+        }
+        // Distances to the side neighbour and from it to the corner, in meters.
+        let (d1, d2) = if side.0 != 0 { (dx, dy) } else { (dy, dx) };
+        let (s1, s2) = (
+            (height - side_height) / d1,
+            (side_height - corner_height) / d2,
+        );
+        let facet_angle = d2.atan2(d1);
+        let (angle, slope) = match s2.atan2(s1) {
+            angle if angle < 0. => (0., s1),
+            angle if angle > facet_angle => (facet_angle, (height - corner_height) / d1.hypot(d2)),
+            angle => (angle, s1.hypot(s2)),
+        };
+        if slope > max_slope {
+            max_slope = slope;
+            // Direction in meters, from the side neighbour turned towards the corner.
+            let (side_x, side_y) = (side.0 as f64, side.1 as f64);
+            let (across_x, across_y) = ((corner.0 - side.0) as f64, (corner.1 - side.1) as f64);
+            let (east, north) = (
+                angle.cos() * side_x + angle.sin() * across_x,
+                angle.cos() * side_y + angle.sin() * across_y,
+            );
+            let azimuth = east.atan2(north).to_degrees().rem_euclid(360.);
+            let share = angle / facet_angle;
+            best = Some((azimuth, [(side_cell, 1. - share), (corner_cell, share)]));
+        }
+    }
+    best
+}
+
+const D8_DIRECTION: Quantity = Quantity {
+    name: "flow_direction",
+    long_name: "D8 flow direction",
+    units: "1",
+    standard_name: None,
+};
+
+const D_INFINITY_DIRECTION: Quantity = Quantity {
+    name: "flow_direction",
+    long_name: "D-infinity flow direction from north",
+    units: "degree",
+    standard_name: None,
+};
+
+const ACCUMULATION: Quantity = Quantity {
+    name: "flow_accumulation",
+    long_name: "number of cells draining through the cell",
+    units: "1",
+    standard_name: None,
+};
+
+const STREAMS: Quantity = Quantity {
+    name: "streams",
+    long_name: "stream network",
+    units: "1",
+    standard_name: None,
+};
+
+const WATERSHED: Quantity = Quantity {
+    name: "watershed",
+    long_name: "watershed of the pour point",
+    units: "1",
+    standard_name: None,
+};
+
+/// Direction of the flow out of each cell of `data`, usually [filled](fill_depressions) first.
+///
+/// The D8 directions use the codes of ESRI, powers of 2 clockwise from 1 for the east to 128
+/// for the north-east, with 0 for the cells without a lower neighbour. The D-infinity
+/// directions are in degrees clockwise from north, -1 without a lower neighbour.
+pub fn flow_direction(data: &ElevationGrid, method: FlowMethod) -> ElevationGrid {
+    let (nx, ny) = data.grid.shape();
+    let mut directions = Vec::with_capacity(nx * ny);
+    for ix in 0..nx {
+        for iy in 0..ny {
+            directions.push(if data.get(ix, iy).is_nan() {
+                f64::NAN
+            } else {
+                match method {
+                    FlowMethod::D8 => d8(data, ix, iy).map_or(0., |k| NEIGHBOURS[k].2 as f64),
+                    FlowMethod::DInfinity => {
+                        d_infinity(data, ix, iy).map_or(-1., |(azimuth, _)| azimuth)
+                    }
+                }
+            });
+        }
+    }
+    let quantity = match method {
+        FlowMethod::D8 => D8_DIRECTION,
+        FlowMethod::DInfinity => D_INFINITY_DIRECTION,
+    };
+    data.derive(directions, quantity)
+}
+
+/// Number of cells draining through each cell of `data`, itself included, usually
+/// [filled](fill_depressions) first.
+pub fn flow_accumulation(data: &ElevationGrid, method: FlowMethod) -> ElevationGrid {
+    let (nx, ny) = data.grid.shape();
+    let mut accumulation: Vec<f64> = data
+        .heights
+        .iter()
+        .map(|h| if h.is_nan() { f64::NAN } else { 1. })
+        .collect();
+    // The flow only goes downhill, so that the cells are done once all their donors are.
+    let mut order: Vec<(usize, usize)> = (0..nx)
+        .flat_map(|ix| (0..ny).map(move |iy| (ix, iy)))
+        .filter(|&(ix, iy)| !data.get(ix, iy).is_nan())
+        .collect();
+    order.sort_by(|a, b| data.get(b.0, b.1).total_cmp(&data.get(a.0, a.1)));
+    for (ix, iy) in order {
+        let flow = accumulation[ix * ny + iy];
+        match method {
+            FlowMethod::D8 => {
+                if let Some(k) = d8(data, ix, iy) {
+                    let (ox, oy, _) = NEIGHBOURS[k];
+                    accumulation[(ix as i64 + ox) as usize * ny + (iy as i64 + oy) as usize] +=
+                        flow;
+                }
+            }
+            FlowMethod::DInfinity => {
+                if let Some((_, receivers)) = d_infinity(data, ix, iy) {
+                    for ((x, y), share) in receivers {
+                        accumulation[x * ny + y] += share * flow;
+                    }
+                }
+            }
+        }
+    }
+    data.derive(accumulation, ACCUMULATION)
+}
+
+/// Stream network of a [`flow_accumulation`]: 1 for the cells draining at least `threshold`
+/// cells, 0 for the others.
+pub fn streams(accumulation: &ElevationGrid, threshold: f64) -> ElevationGrid {
+    let values = accumulation
+        .heights
+        .iter()
+        .map(|&a| {
+            if a.is_nan() {
+                a
+            } else {
+                f64::from(u8::from(a >= threshold))
+            }
+        })
+        .collect();
+    accumulation.derive(values, STREAMS)
+}
+
+/// Cell of the largest [`flow_accumulation`] within `radius` meters of the point (`ix`, `iy`),
+/// to move a pour point onto the stream it is meant to be on. Use a D8 accumulation for a
+/// [`watershed`], which follows the D8 directions.
+pub fn snap_pour_point(
+    accumulation: &ElevationGrid,
+    (ix, iy): (usize, usize),
+    radius: f64,
+) -> (usize, usize) {
+    let (nx, ny) = accumulation.grid.shape();
+    let (dx, dy) = accumulation.grid.cell_size(iy);
+    let (rx, ry) = ((radius / dx) as usize, (radius / dy) as usize);
+    let mut best = (ix, iy);
+    for x in ix.saturating_sub(rx)..=(ix + rx).min(nx - 1) {
+        for y in iy.saturating_sub(ry)..=(iy + ry).min(ny - 1) {
+            let within = ((x as f64 - ix as f64) * dx).hypot((y as f64 - iy as f64) * dy) <= radius;
+            if within && accumulation.get(x, y) > accumulation.get(best.0, best.1) {
+                best = (x, y);
+            }
+        }
+    }
+    best
+}
+
+/// Watershed of the pour point (`ix`, `iy`): 1 for the cells of `data` draining to it by the D8
+/// directions, whatever the [`FlowMethod`] of the other products, 0 for the others. `data` is
+/// usually [filled](fill_depressions) first.
+pub fn watershed(data: &ElevationGrid, (ix, iy): (usize, usize)) -> ElevationGrid {
+    let ny = data.grid.y.len();
+    let mut mask: Vec<f64> = data
+        .heights
+        .iter()
+        .map(|h| if h.is_nan() { f64::NAN } else { 0. })
+        .collect();
+    if data.get(ix, iy).is_nan() {
+        return data.derive(mask, WATERSHED);
+    }
+    mask[ix * ny + iy] = 1.;
+    // Walk up the flow from the pour point, through the neighbours draining to each cell.
+    let mut queue = VecDeque::from([(ix, iy)]);
+    while let Some((x, y)) = queue.pop_front() {
+        for &(ox, oy, _) in &NEIGHBOURS {
+            let Some((donor_x, donor_y)) = neighbour(data, x, y, (ox, oy)) else {
+                continue;
+            };
+            if mask[donor_x * ny + donor_y] != 0. {
+                continue;
+            }
+            let drains_here = d8(data, donor_x, donor_y).is_some_and(|k| {
+                let (dx, dy, _) = NEIGHBOURS[k];
+                (dx, dy) == (-ox, -oy)
+            });
+            if drains_here {
+                mask[donor_x * ny + donor_y] = 1.;
+                queue.push_back((donor_x, donor_y));
+            }
+        }
+    }
+    data.derive(mask, WATERSHED)
+}
+
+/// Outline of the cells of `mask` equal to 1, such as a [`watershed`], as polygons along the
+/// edges of the cells, each cell being centered on its point.
+pub fn outline(mask: &ElevationGrid) -> Vec<Polygon> {
+    let (nx, ny) = mask.grid.shape();
+    let inside = |ix: i64, iy: i64| {
+        ix >= 0
+            && iy >= 0
+            && (ix as usize) < nx
+            && (iy as usize) < ny
+            && mask.get(ix as usize, iy as usize) == 1.
+    };
+
+    // Edges between the cells inside and outside, on the lattice of the corners of the cells
+    // where the cell (ix, iy) spans from (ix, iy) to (ix + 1, iy + 1), with the inside on the
+    // left.
+    let mut edges: BTreeMap<(i64, i64), Vec<(i64, i64)>> = BTreeMap::new();
+    for ix in 0..nx as i64 {
+        for iy in 0..ny as i64 {
+            if !inside(ix, iy) {
+                continue;
+            }
+            let sides = [
+                ((0, -1), (ix, iy), (ix + 1, iy)),
+                ((1, 0), (ix + 1, iy), (ix + 1, iy + 1)),
+                ((0, 1), (ix + 1, iy + 1), (ix, iy + 1)),
+                ((-1, 0), (ix, iy + 1), (ix, iy)),
+            ];
+            for ((ox, oy), start, end) in sides {
+                if !inside(ix + ox, iy + oy) {
+                    edges.entry(start).or_default().push(end);
+                }
+            }
+        }
+    }
+
+    // Rings following the edges, turning left first where two cells only touch by a corner.
+    // Each ring starts from its lowest corner, which is never such a pinch.
+    let mut rings = Vec::new();
+    while let Some(&start) = edges.keys().next() {
+        let mut ring = vec![start];
+        let (mut previous, mut current) = (start, edges_pop(&mut edges, start, None));
+        while current != start {
+            ring.push(current);
+            let direction = (current.0 - previous.0, current.1 - previous.1);
+            let next = edges_pop(&mut edges, current, Some(direction));
+            (previous, current) = (current, next);
+        }
+        ring.push(start);
+        rings.extend(split(ring).into_iter().map(simplify));
+    }
+
+    // Counterclockwise rings are the outer ones, each clockwise ring is a hole of the smallest
+    // outer ring around it.
+    let (outer, holes): (Vec<_>, Vec<_>) =
+        rings.into_iter().partition(|ring| signed_area(ring) > 0.);
+    let mut polygons: Vec<(Ring, Vec<Ring>)> =
+        outer.into_iter().map(|ring| (ring, Vec::new())).collect();
+    for hole in holes {
+        // A point just inside the cells around the hole, off the edges of the lattice.
+        let (a, b) = (hole[0], hole[1]);
+        let point = (
+            0.5 * (a.0 + b.0) as f64 - 0.25 * (b.1 - a.1) as f64,
+            0.5 * (a.1 + b.1) as f64 + 0.25 * (b.0 - a.0) as f64,
+        );
+        let owner = polygons
+            .iter_mut()
+            .filter(|(ring, _)| contains(ring, point))
+            .min_by(|a, b| signed_area(&a.0).total_cmp(&signed_area(&b.0)));
+        if let Some((_, polygon_holes)) = owner {
+            polygon_holes.push(hole);
+        }
+    }
+
+    let grid = &mask.grid;
+    let (dx, dy) = grid.spacing();
+    let (x0, y0) = (
+        grid.x.first().copied().unwrap_or_default(),
+        grid.y.first().copied().unwrap_or_default(),
+    );
+    let to_crs = |ring: &[(i64, i64)]| -> Vec<(f64, f64)> {
+        ring.iter()
+            .map(|&(cx, cy)| (x0 + (cx as f64 - 0.5) * dx, y0 + (cy as f64 - 0.5) * dy))
+            .collect()
+    };
+    let (cell_x, cell_y) = grid.cell_size(ny / 2);
+    polygons
+        .iter()
+        .map(|(exterior, holes)| Polygon {
+            exterior: to_crs(exterior),
+            holes: holes.iter().map(|hole| to_crs(hole)).collect(),
+            area: (signed_area(exterior) + holes.iter().map(|hole| signed_area(hole)).sum::<f64>())
+                * cell_x
+                * cell_y,
+        })
+        .collect()
+}
+
+// Ring on the lattice of the corners of the cells.
+type Ring = Vec<(i64, i64)>;
+
+// Take an edge starting at `corner`, the leftmost one coming from the given direction.
+fn edges_pop(
+    edges: &mut BTreeMap<(i64, i64), Vec<(i64, i64)>>,
+    corner: (i64, i64),
+    direction: Option<(i64, i64)>,
+) -> (i64, i64) {
+    let ends = edges
+        .get_mut(&corner)
+        .expect("The outline rings are closed");
+    let position = match direction {
+        Some((dx, dy)) if ends.len() > 1 => ends
+            .iter()
+            .position(|end| (end.0 - corner.0, end.1 - corner.1) == (-dy, dx))
+            .unwrap_or(0),
+        _ => 0,
+    };
+    let end = ends.swap_remove(position);
+    if ends.is_empty() {
+        edges.remove(&corner);
+    }
+    end
+}
+
+// Simple rings of a closed ring passing several times through some corners, cut at them. Such
+// a loop around cells outside of the mask turns clockwise, making it a hole.
+fn split(ring: Ring) -> Vec<Ring> {
+    let mut rings = Vec::new();
+    let mut current: Ring = Vec::with_capacity(ring.len());
+    let mut positions = HashMap::new();
+    for &corner in &ring[..ring.len() - 1] {
+        if let Some(position) = positions.get(&corner).copied() {
+            let mut inner = current.split_off(position);
+            for removed in &inner {
+                positions.remove(removed);
+            }
+            inner.push(corner);
+            rings.push(inner);
+        }
+        positions.insert(corner, current.len());
+        current.push(corner);
+    }
+    current.push(current[0]);
+    rings.push(current);
+    rings
+}
+
+// Ring without the points in the middle of straight runs.
+fn simplify(ring: Ring) -> Ring {
+    let n = ring.len() - 1;
+    let collinear = |i: usize| {
+        let (a, b, c) = (ring[(i + n - 1) % n], ring[i], ring[(i + 1) % n]);
+        (b.0 - a.0) * (c.1 - b.1) == (b.1 - a.1) * (c.0 - b.0)
+    };
+    let mut simplified: Vec<_> = (0..n).filter(|&i| !collinear(i)).map(|i| ring[i]).collect();
+    simplified.push(simplified[0]);
+    simplified
+}
+
+// Area of a closed ring, positive counterclockwise.
+fn signed_area(ring: &[(i64, i64)]) -> f64 {
+    ring.windows(2)
+        .map(|pair| (pair[0].0 * pair[1].1 - pair[1].0 * pair[0].1) as f64)
+        .sum::<f64>()
+        / 2.
+}
+
+// Whether a closed ring contains a point, by counting the crossings of a ray to the east.
+fn contains(ring: &[(i64, i64)], (x, y): (f64, f64)) -> bool {
+    let mut inside = false;
+    for pair in ring.windows(2) {
+        let ((x0, y0), (x1, y1)) = (
+            (pair[0].0 as f64, pair[0].1 as f64),
+            (pair[1].0 as f64, pair[1].1 as f64),
+        );
+        if (y0 > y) != (y1 > y) && x < x0 + (y - y0) / (y1 - y0) * (x1 - x0) {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Grid;
+    use crate::projection::Crs;
+
+    // Map of nx x ny points 1 m apart in Lambert-93, with the height given by `height(ix, iy)`.
+    fn map(nx: usize, ny: usize, height: impl Fn(usize, usize) -> f64) -> ElevationGrid {
+        let grid = Grid {
+            crs: Crs::lambert93(),
+            center: (3., 46.5),
+            x: (0..nx).map(|ix| 700_000. + ix as f64).collect(),
+            y: (0..ny).map(|iy| 6_600_000. + iy as f64).collect(),
+            x_resolution: 1.,
+            y_resolution: 1.,
+        };
+        let heights = (0..nx)
+            .flat_map(|ix| (0..ny).map(move |iy| (ix, iy)))
+            .map(|(ix, iy)| height(ix, iy))
+            .collect();
+        ElevationGrid::new(grid, heights)
+    }
+
+    // V-shaped valley along ix = 2, 10 m deep on each side, sloping 1 m per cell to the south.
+    fn valley() -> ElevationGrid {
+        map(5, 7, |ix, iy| 10. * (ix as f64 - 2.).abs() + iy as f64)
+    }
+
+    #[test]
+    fn fill_pit() {
+        let data = map(5, 5, |ix, iy| if (ix, iy) == (2, 2) { 1. } else { 10. });
+        let filled = fill_depressions(&data);
+        assert!(filled.get(2, 2) > 10.);
+        assert!(filled.get(2, 2) < 10. + 3. * FLAT_INCREMENT);
+        assert_eq!(filled.get(0, 0), 10.);
+        // Every cell inside drains somewhere.
+        let directions = flow_direction(&filled, FlowMethod::D8);
+        for ix in 1..4 {
+            for iy in 1..4 {
+                assert_ne!(directions.get(ix, iy), 0., "no outflow at ({}, {})", ix, iy);
+            }
+        }
+    }
+
+    #[test]
+    fn valley_d8() {
+        let data = valley();
+        let directions = flow_direction(&data, FlowMethod::D8);
+        assert_eq!(directions.get(2, 3), 4.); // south, down the valley
+        assert_eq!(directions.get(0, 3), 1.); // east, into the valley
+        assert_eq!(directions.get(4, 3), 16.); // west
+        assert_eq!(directions.get(2, 0), 0.); // outlet
+        let accumulation = flow_accumulation(&data, FlowMethod::D8);
+        assert_eq!(accumulation.get(2, 0), 35.);
+        assert_eq!(accumulation.get(0, 3), 1.);
+    }
+
+    #[test]
+    fn valley_d_infinity() {
+        let data = valley();
+        let directions = flow_direction(&data, FlowMethod::DInfinity);
+        assert!((directions.get(2, 3) - 180.).abs() < 1e-9);
+        // East, turned towards the south by the fall of 1 m per 10 m across the slope.
+        let expected = 90. + 0.1f64.atan().to_degrees();
+        assert!((directions.get(0, 3) - expected).abs() < 1e-9);
+        assert!((directions.get(4, 3) - (360. - expected)).abs() < 1e-9);
+        assert_eq!(directions.get(2, 0), -1.);
+        let accumulation = flow_accumulation(&data, FlowMethod::DInfinity);
+        assert!((accumulation.get(2, 0) - 35.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn outline_hole_and_diagonal() {
+        // A 3 x 3 block with a hole in its middle, and a cell touching its corner.
+        let mask = map(6, 6, |ix, iy| {
+            let block = (1..4).contains(&ix) && (1..4).contains(&iy) && (ix, iy) != (2, 2);
+            if block || (ix, iy) == (4, 4) {
+                1.
+            } else {
+                0.
+            }
+        });
+        let mut polygons = outline(&mask);
+        polygons.sort_by(|a, b| a.area.total_cmp(&b.area));
+        assert_eq!(polygons.len(), 2);
+
+        let cell = &polygons[0];
+        assert!((cell.area - 1.).abs() < 1e-9);
+        assert_eq!(cell.exterior.len(), 5);
+        assert!(cell.holes.is_empty());
+
+        let block = &polygons[1];
+        assert!((block.area - 8.).abs() < 1e-9);
+        assert_eq!(block.exterior.len(), 5);
+        assert_eq!(block.holes.len(), 1);
+        assert_eq!(block.holes[0].len(), 5);
+        assert_eq!(block.exterior.first(), block.exterior.last());
+    }
+}
@@ -0,0 +1,222 @@
+//! Readers of the elevation grids saved as rasters, to derive products without fetching again.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::Tag;
+
+use crate::grid::{ElevationGrid, Grid};
+use crate::output::OutputFormat;
+use crate::projection::Crs;
+
+// GeoTIFF keys giving the EPSG code of the CRS.
+const GEOGRAPHIC_TYPE: u16 = 2048;
+const PROJECTED_CS_TYPE: u16 = 3072;
+
+/// Read the heights saved at `path`, a GeoTIFF (`.tif`) or an ESRI ASCII Grid (`.asc`).
+pub fn read_raster(path: &str) -> Result<ElevationGrid> {
+    match OutputFormat::from_extension(path) {
+        Some(OutputFormat::GeoTiff) => read_geotiff(path),
+        Some(OutputFormat::Asc) => read_ascii_grid(path),
+        _ => anyhow::bail!("Cannot read the raster {}, expected .tif or .asc", path),
+    }
+}
+
+/// Read a single-band GeoTIFF, such as the GeoTIFF and cloud-optimized GeoTIFF outputs, in a
+/// CRS supported by [`Crs::from_epsg`]. The values equal to its GDAL no-data value are NaN.
+pub fn read_geotiff(path: &str) -> Result<ElevationGrid> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    let mut decoder = Decoder::new(BufReader::new(file))
+        .with_context(|| format!("Failed to read the TIFF {}", path))?;
+    let (width, height) = decoder.dimensions()?;
+    let scale = decoder
+        .get_tag_f64_vec(Tag::ModelPixelScaleTag)
+        .with_context(|| format!("No pixel scale in {}, not a GeoTIFF", path))?;
+    let tiepoint = decoder
+        .get_tag_f64_vec(Tag::ModelTiepointTag)
+        .with_context(|| format!("No tie point in {}, not a GeoTIFF", path))?;
+    let keys = decoder
+        .get_tag_u16_vec(Tag::GeoKeyDirectoryTag)
+        .with_context(|| format!("No GeoKey directory in {}", path))?;
+    let nodata = decoder
+        .find_tag(Tag::GdalNodata)?
+        .and_then(|value| value.into_string().ok())
+        .and_then(|value| value.trim_matches(char::from(0)).trim().parse::<f64>().ok());
+    let (&[dx, dy, ..], &[0., 0., _, west, north, ..]) = (&scale[..], &tiepoint[..]) else {
+        anyhow::bail!("Unsupported georeferencing in {}", path);
+    };
+
+    // Entries of 4 values after the header: key, location, count and value.
+    let epsg = keys
+        .chunks_exact(4)
+        .skip(1)
+        .find(|key| matches!(key[0], GEOGRAPHIC_TYPE | PROJECTED_CS_TYPE) && key[1] == 0)
+        .map(|key| key[3] as u32)
+        .with_context(|| format!("No EPSG code in the GeoKeys of {}", path))?;
+    let crs = Crs::from_epsg(epsg)
+        .with_context(|| format!("Unsupported CRS EPSG:{} of {}", epsg, path))?;
+
+    let values: Vec<f64> = match decoder.read_image()? {
+        DecodingResult::F32(values) => values.into_iter().map(f64::from).collect(),
+        DecodingResult::F64(values) => values,
+        _ => anyhow::bail!("Expected floating point heights in {}", path),
+    };
+    let (nx, ny) = (width as usize, height as usize);
+    anyhow::ensure!(
+        values.len() == nx * ny,
+        "Expected a single band in {}",
+        path
+    );
+    let grid = Grid::from_origin(
+        crs,
+        (west + 0.5 * dx, north - (ny as f64 - 0.5) * dy),
+        (dx, dy),
+        nx,
+        ny,
+    );
+    Ok(from_rows_north_up(grid, &values, nodata))
+}
+
+/// Read an ESRI ASCII Grid, in the CRS of the WKT of its `.prj` file. The values equal to its
+/// `NODATA_value` are NaN.
+pub fn read_ascii_grid(path: &str) -> Result<ElevationGrid> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+    let prj = Path::new(path).with_extension("prj");
+    let wkt = std::fs::read_to_string(&prj)
+        .with_context(|| format!("Failed to read the CRS of {} from {}", path, prj.display()))?;
+    let crs = crs_of_wkt(&wkt).with_context(|| format!("Unsupported CRS in {}", prj.display()))?;
+
+    // Header lines of a keyword and a value, then the rows of values.
+    let mut tokens = text.split_ascii_whitespace().peekable();
+    let mut header = Vec::new();
+    while let Some(key) = tokens.next_if(|token| token.starts_with(char::is_alphabetic)) {
+        let value: f64 = tokens
+            .next()
+            .and_then(|value| value.parse().ok())
+            .with_context(|| format!("Invalid {} in {}", key, path))?;
+        header.push((key.to_ascii_lowercase(), value));
+    }
+    let field = |name: &str| {
+        header
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| *value)
+    };
+    let required = |name: &str| field(name).with_context(|| format!("No {} in {}", name, path));
+    let (nx, ny) = (required("ncols")? as usize, required("nrows")? as usize);
+    let (dx, dy) = match field("cellsize") {
+        Some(cellsize) => (cellsize, cellsize),
+        None => (required("dx")?, required("dy")?),
+    };
+    // The lower-left corner is given either as the corner of the cell or as its center.
+    let x0 = match field("xllcenter") {
+        Some(x) => x,
+        None => required("xllcorner")? + 0.5 * dx,
+    };
+    let y0 = match field("yllcenter") {
+        Some(y) => y,
+        None => required("yllcorner")? + 0.5 * dy,
+    };
+
+    let values = tokens
+        .map(|value| value.parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid value in {}", path))?;
+    anyhow::ensure!(
+        values.len() == nx * ny,
+        "Expected {} values in {}, found {}",
+        nx * ny,
+        path,
+        values.len()
+    );
+    let grid = Grid::from_origin(crs, (x0, y0), (dx, dy), nx, ny);
+    Ok(from_rows_north_up(grid, &values, field("nodata_value")))
+}
+
+// CRS of a WKT description, from the EPSG authority at its end.
+fn crs_of_wkt(wkt: &str) -> Option<Crs> {
+    const AUTHORITY: &str = "AUTHORITY[\"EPSG\",\"";
+    let start = wkt.rfind(AUTHORITY)? + AUTHORITY.len();
+    let code = wkt[start..].split('"').next()?.parse().ok()?;
+    Crs::from_epsg(code)
+}
+
+// Elevation grid of the north-up rows of a raster, `nodata` values being NaN.
+fn from_rows_north_up(grid: Grid, rows: &[f64], nodata: Option<f64>) -> ElevationGrid {
+    let (nx, ny) = grid.shape();
+    let heights = (0..nx)
+        .flat_map(|ix| (0..ny).map(move |iy| rows[(ny - 1 - iy) * nx + ix]))
+        .map(|h| if Some(h) == nodata { f64::NAN } else { h })
+        .collect();
+    ElevationGrid::new(grid, heights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{save_ascii_grid, save_geotiff, Compression};
+
+    fn map(crs: Crs) -> ElevationGrid {
+        let grid = match crs {
+            Crs::Wgs84 => Grid::from_origin(crs, (3., 46.5), (0.001, 0.0005), 3, 2),
+            Crs::Projected { .. } => {
+                Grid::from_origin(crs, (700_000., 6_600_000.), (100., 100.), 3, 2)
+            }
+        };
+        ElevationGrid::new(grid, vec![f64::NAN, 2., 3., 4., 5., 6.5])
+    }
+
+    // Save `data` with `save` at a temporary path of `extension`, and read it back.
+    fn round_trip(
+        data: &ElevationGrid,
+        name: &str,
+        extension: &str,
+        save: fn(&str, &ElevationGrid) -> Result<()>,
+    ) -> ElevationGrid {
+        let path =
+            std::env::temp_dir().join(format!("{}-{}.{}", name, std::process::id(), extension));
+        let path = path.to_str().unwrap();
+        save(path, data).unwrap();
+        let read = read_raster(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let _ = std::fs::remove_file(Path::new(path).with_extension("prj"));
+        read
+    }
+
+    fn assert_same(read: &ElevationGrid, data: &ElevationGrid) {
+        assert_eq!(read.grid.crs, data.grid.crs);
+        assert_eq!(read.grid.shape(), data.grid.shape());
+        for (a, b) in read.grid.x.iter().zip(&data.grid.x) {
+            assert!((a - b).abs() < 1e-9, "x {} != {}", a, b);
+        }
+        for (a, b) in read.grid.y.iter().zip(&data.grid.y) {
+            assert!((a - b).abs() < 1e-9, "y {} != {}", a, b);
+        }
+        assert!(read.heights[0].is_nan());
+        assert_eq!(read.heights[1..], data.heights[1..]);
+    }
+
+    #[test]
+    fn geotiff() {
+        for crs in [Crs::Wgs84, Crs::lambert93()] {
+            let data = map(crs);
+            let save =
+                |path: &str, data: &ElevationGrid| save_geotiff(path, data, Compression::None);
+            assert_same(&round_trip(&data, "geotiff", "tif", save), &data);
+        }
+    }
+
+    #[test]
+    fn ascii_grid() {
+        for crs in [Crs::Wgs84, Crs::lambert93()] {
+            let data = map(crs);
+            assert_same(
+                &round_trip(&data, "ascii-grid", "asc", save_ascii_grid),
+                &data,
+            );
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! Fetch elevation maps from the IGN altimetry API.
+//!
+//! The crate builds a regular grid of points around a GPS coordinate, fetches the
+//! elevation at every point and saves the result as an HDF5 file, a GeoTIFF or an image.
+//! Shaded relief, contour lines, viewsheds, watersheds and other terrain products can be
+//! derived from it, and elevation profiles fetched along a route.
+
+pub mod cache;
+pub mod checkpoint;
+pub mod colormap;
+pub mod contour;
+pub mod fetch;
+pub mod grid;
+pub mod hydrology;
+pub mod input;
+pub mod output;
+pub mod profile;
+pub mod projection;
+pub mod provider;
+pub mod retry;
+pub mod terrain;
+pub mod viewshed;
+
+pub use cache::{CachedProvider, ElevationCache};
+pub use checkpoint::Checkpoint;
+pub use colormap::ColorRamp;
+pub use contour::{contours, Contour, ContourOptions};
+pub use fetch::{
+    fetch_elevations, fetch_missing_elevations, BatchError, Elevations, FetchOptions, BATCH_SIZE,
+};
+pub use grid::{calculate_xy_positions, BoundingBox, ElevationGrid, Grid, LatLon, Quantity};
+pub use hydrology::{
+    fill_depressions, flow_accumulation, flow_direction, outline, snap_pour_point, streams,
+    watershed, FlowMethod, Polygon,
+};
+pub use input::{read_ascii_grid, read_geotiff, read_raster};
+pub use output::{
+    save_as, save_ascii_grid, save_cog, save_color_relief, save_contours, save_contours_geojson,
+    save_contours_shapefile, save_contours_svg, save_elevation_data, save_geotiff,
+    save_heightmap_image, save_hillshade_image, save_netcdf, save_polygons, save_polygons_geojson,
+    save_polygons_shapefile, save_polygons_svg, save_xyz, Compression, ImageEncoding, ImageOptions,
+    ImageSidecar, Normalization, OutputFormat, OutputOptions, VectorFormat,
+};
+pub use profile::{densify, read_route, save_profile_csv, summarize, ProfilePoint, ProfileSummary};
+pub use projection::Crs;
+pub use provider::{ElevationProvider, IgnProvider};
+pub use retry::{RateLimiter, RetryPolicy};
+pub use terrain::{hillshade, terrain_product, HillshadeOptions, TerrainProduct};
+pub use viewshed::{viewshed, visibility_count, ViewshedOptions};
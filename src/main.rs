@@ -1,170 +1,798 @@
+use std::path::PathBuf;
+
 use anyhow::{Context, Result};
-use clap::Parser;
-use hdf5::File;
-use image::GrayImage;
+use clap::{ArgGroup, Parser, Subcommand};
 use indicatif::ProgressBar;
-use reqwest::blocking::get;
-use serde::Deserialize;
-
-const METERS_PER_LAT_DEGREE: f64 = 111000.;
-const BATCH_SIZE: usize = 50;
 
-#[derive(Debug, Deserialize)]
-struct ElevationResponse {
-    elevations: Vec<f64>,
-}
+use ign_heightmap::cache::{CachedProvider, ElevationCache, CACHE_ENV, DEFAULT_MAX_ENTRIES};
+use ign_heightmap::provider::{DEFAULT_ENDPOINT, DEFAULT_RESOURCE, ENDPOINT_ENV};
+use ign_heightmap::retry::IGN_REQUESTS_PER_SECOND;
+use ign_heightmap::viewshed::DEFAULT_REFRACTION;
+use ign_heightmap::{
+    contours, densify, fetch_elevations, fetch_missing_elevations, fill_depressions,
+    flow_accumulation, flow_direction, hillshade, outline, read_raster, read_route, save_as,
+    save_color_relief, save_contours, save_heightmap_image, save_hillshade_image, save_polygons,
+    save_profile_csv, snap_pour_point, streams, summarize, terrain_product, viewshed,
+    visibility_count, watershed, BoundingBox, Checkpoint, ColorRamp, Compression, ContourOptions,
+    Crs, ElevationGrid, ElevationProvider, FetchOptions, FlowMethod, Grid, HillshadeOptions,
+    IgnProvider, ImageEncoding, ImageOptions, LatLon, Normalization, OutputFormat, OutputOptions,
+    RetryPolicy, TerrainProduct, VectorFormat, ViewshedOptions,
+};
 
 #[derive(Parser, Debug)]
-#[command(author, version, about = "Extract elevation maps from IGN API")]
+#[command(
+    author,
+    version,
+    about = "Extract elevation maps from IGN API",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    group(
+        ArgGroup::new("flow")
+            .args(["flow_direction", "flow_accumulation", "streams"])
+            .multiple(true)
+    ),
+    group(ArgGroup::new("basin").args(["watershed", "watershed_outline"]).multiple(true))
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Latitude of the map center
-    #[arg(required(true))]
-    latitude: f64,
+    #[arg(required_unless_present("bbox"))]
+    latitude: Option<f64>,
 
     /// Longitude of the map center
-    #[arg(required(true))]
-    longitude: f64,
+    #[arg(required_unless_present("bbox"))]
+    longitude: Option<f64>,
 
     /// Size of the map in meters
     #[arg(short, long, default_value = "1000.")]
     size: f64,
 
+    /// Width of the map in meters, instead of the size
+    #[arg(long)]
+    width: Option<f64>,
+
+    /// Height of the map in meters, instead of the size
+    #[arg(long)]
+    height: Option<f64>,
+
+    /// Area of the map as min_lon,min_lat,max_lon,max_lat, instead of a center and a size
+    #[arg(long, allow_hyphen_values(true), conflicts_with_all(["latitude", "longitude", "size", "width", "height"]))]
+    bbox: Option<BoundingBox>,
+
+    /// Lay the grid out in a projected CRS given as EPSG:<code> instead of longitude/latitude:
+    /// Lambert-93 (EPSG:2154), CC zones (EPSG:3942-3950) or UTM (EPSG:258xx, 326xx, 327xx)
+    #[arg(long, default_value = "EPSG:4326")]
+    crs: Crs,
+
+    /// Lay the grid out in Lambert-93, same as --crs EPSG:2154
+    #[arg(long, conflicts_with("crs"))]
+    lambert93: bool,
+
     /// Resolution of the map in meters
     #[arg(short, long, default_value = "50.")]
     resolution: f64,
 
+    /// Resolution of the map along the longitude in meters, instead of the resolution
+    #[arg(long)]
+    x_resolution: Option<f64>,
+
+    /// Resolution of the map along the latitude in meters, instead of the resolution
+    #[arg(long)]
+    y_resolution: Option<f64>,
+
     /// Path of the output
     #[arg(short, long, default_value = "heights.dat")]
     output: String,
 
+    /// Format of the output: hdf5, netcdf, geotiff, cog (cloud-optimized GeoTIFF), asc (ESRI
+    /// ASCII Grid) or xyz, guessed from the output extension by default
+    #[arg(short, long)]
+    format: Option<OutputFormat>,
+
+    /// Compression of the GeoTIFF outputs: none, deflate or lzw [default: none for geotiff,
+    /// deflate for cog]
+    #[arg(long)]
+    compression: Option<Compression>,
+
     /// Path of the image
     #[arg(long, default_value = None)]
     image: Option<String>,
+
+    /// Encoding of the image: gray16 (16-bit grayscale), float32 (TIFF), terrain-rgb or
+    /// terrarium (RGB PNG for web maps), guessed from the image extension by default
+    #[arg(long)]
+    image_encoding: Option<ImageEncoding>,
+
+    /// Mapping of the heights to the image values: minmax, range:<min>,<max> in meters, or
+    /// scale:<factor> for the heights in meters times the factor, for the gray16 and float32
+    /// encodings
+    #[arg(long, default_value = "minmax", allow_hyphen_values(true))]
+    image_normalization: Normalization,
+
+    /// Render the image as a colour relief with a built-in ramp (terrain, hypsometric, gray,
+    /// viridis) or a GDAL color-relief file
+    #[arg(long, requires("image"), conflicts_with("image_encoding"))]
+    colormap: Option<String>,
+
+    /// Path of a hillshade: a raster for the extensions of the output formats, an 8-bit
+    /// grayscale image otherwise
+    #[arg(long)]
+    hillshade: Option<String>,
+
+    /// Vertical exaggeration of the hillshade
+    #[arg(long, default_value = "1.")]
+    z_factor: f64,
+
+    /// Direction of the sun of the hillshade, in degrees clockwise from north
+    #[arg(long, default_value = "315.")]
+    azimuth: f64,
+
+    /// Height of the sun of the hillshade above the horizon, in degrees
+    #[arg(long, default_value = "45.")]
+    altitude: f64,
+
+    /// Light the hillshade from several directions instead of the azimuth
+    #[arg(long, conflicts_with("azimuth"))]
+    multidirectional: bool,
+
+    /// Path of the slope in degrees, in the output format of its extension
+    #[arg(long)]
+    slope: Option<String>,
+
+    /// Path of the slope in percent, in the output format of its extension
+    #[arg(long)]
+    slope_percent: Option<String>,
+
+    /// Path of the aspect, the downslope direction in degrees from north
+    #[arg(long)]
+    aspect: Option<String>,
+
+    /// Path of the profile curvature, along the slope
+    #[arg(long)]
+    profile_curvature: Option<String>,
+
+    /// Path of the plan curvature, across the slope
+    #[arg(long)]
+    plan_curvature: Option<String>,
+
+    /// Path of the contour lines: GeoJSON (.geojson, .json), ESRI Shapefile (.shp) or SVG (.svg)
+    #[arg(long)]
+    contours: Option<String>,
+
+    /// Height difference between two contour lines, in meters
+    #[arg(long, default_value = "10.", requires("contours"))]
+    contour_interval: f64,
+
+    /// Height of a contour line, the others being at multiples of the interval from it
+    #[arg(
+        long,
+        default_value = "0.",
+        allow_hyphen_values(true),
+        requires("contours")
+    )]
+    contour_base: f64,
+
+    /// Mark every Nth contour line from the base as an index contour, 0 for none
+    #[arg(long, default_value = "5", requires("contours"))]
+    index_contours: usize,
+
+    /// Number of smoothing passes of the contour lines
+    #[arg(long, default_value = "0", requires("contours"))]
+    contour_smoothing: usize,
+
+    #[command(flatten)]
+    visibility: VisibilityArgs,
+
+    /// Path of the heights with the depressions filled, in the output format of its extension
+    #[arg(long)]
+    filled: Option<String>,
+
+    /// Path of the flow direction: D8 codes of ESRI (1 east to 128 north-east clockwise, 0
+    /// without outflow) or D-infinity degrees from north (-1 without outflow)
+    #[arg(long)]
+    flow_direction: Option<String>,
+
+    /// Path of the flow accumulation, the number of cells draining through each cell
+    #[arg(long)]
+    flow_accumulation: Option<String>,
+
+    /// Routing of the flow direction and accumulation: d8 or dinf (D-infinity)
+    #[arg(long, default_value = "d8", requires("flow"))]
+    flow_method: FlowMethod,
+
+    /// Path of the stream network, 1 on the cells draining at least the stream threshold
+    #[arg(long)]
+    streams: Option<String>,
+
+    /// Number of cells draining through a cell for it to be on a stream
+    #[arg(long, default_value = "100.", requires("streams"))]
+    stream_threshold: f64,
+
+    /// Path of the watershed of the pour point, 1 on the cells draining to it
+    #[arg(long, requires("pour_point"))]
+    watershed: Option<String>,
+
+    /// Path of the outline of the watershed: GeoJSON (.geojson, .json), ESRI Shapefile (.shp) or
+    /// SVG (.svg)
+    #[arg(long, requires("pour_point"))]
+    watershed_outline: Option<String>,
+
+    /// Outlet of the watershed as latitude,longitude
+    #[arg(long, allow_hyphen_values(true), requires("basin"))]
+    pour_point: Option<LatLon>,
+
+    /// Move the pour point to the largest D8 flow accumulation within this distance, in meters
+    #[arg(long, default_value = "0.", requires("pour_point"))]
+    snap_distance: f64,
+
+    /// URL of the altimetry service
+    #[arg(long, env = ENDPOINT_ENV, default_value = DEFAULT_ENDPOINT, global = true)]
+    endpoint: String,
+
+    /// Elevation dataset of the altimetry service
+    #[arg(long, default_value = DEFAULT_RESOURCE, global = true)]
+    resource: String,
+
+    /// Number of retries of a request after a transient failure
+    #[arg(long, default_value = "5", global = true)]
+    retries: u32,
+
+    /// Maximum number of requests per second, 0 for no limit
+    #[arg(long, default_value_t = IGN_REQUESTS_PER_SECOND, global = true)]
+    rate_limit: f64,
+
+    /// Number of concurrent requests
+    #[arg(short = 'j', long, default_value = "4", global = true)]
+    workers: usize,
+
+    /// Only fetch the batches missing from the checkpoint of a previous run
+    #[arg(long)]
+    resume: bool,
+
+    /// Save the outputs even if some batches failed, with NaN for the missing heights
+    #[arg(long, global = true)]
+    allow_partial: bool,
+
+    /// Path of the elevation cache, consulted before the altimetry service
+    #[arg(long, env = CACHE_ENV, global = true)]
+    cache: Option<PathBuf>,
+
+    /// Maximum number of elevations kept in the cache, the least recently used are evicted
+    #[arg(long, default_value_t = DEFAULT_MAX_ENTRIES, global = true)]
+    cache_max_entries: u64,
 }
 
-// Calculate the x and y positions of the map points as lattitude/longitude.
-fn calculate_xy_positions(
-    latitude: f64,
-    longitude: f64,
-    size: f64,
-    resolution: f64,
-) -> (Vec<f64>, Vec<f64>) {
-    let meters_per_lon_degree: f64 =
-        METERS_PER_LAT_DEGREE * (2. * std::f64::consts::PI * latitude / 360.).cos();
-    let mut x = Vec::<f64>::new();
-    let mut y = Vec::<f64>::new();
-    let map_size = f64::floor(size / resolution) as i64;
-    for i in 0..map_size {
-        x.push(longitude - (0.5 * size - (i as f64) * resolution) / meters_per_lon_degree);
-        y.push(latitude - (0.5 * size - (i as f64) * resolution) / METERS_PER_LAT_DEGREE);
-    }
-    (x, y)
+// Options of the viewshed, on the fetched map or on a saved one.
+#[derive(clap::Args, Debug)]
+#[group(skip)]
+#[command(group(ArgGroup::new("visibility").args(["viewshed", "visibility_count"]).multiple(true)))]
+struct VisibilityArgs {
+    /// Path of the visibility mask, 1 where an observer sees the ground and 0 elsewhere, in the
+    /// output format of its extension
+    #[arg(long, requires("observers"))]
+    viewshed: Option<String>,
+
+    /// Path of the cumulative visibility, the number of observers seeing each point
+    #[arg(long, requires("observers"))]
+    visibility_count: Option<String>,
+
+    /// Observer of the viewshed as latitude,longitude, repeated for several observers
+    #[arg(long = "observer", allow_hyphen_values(true), requires("visibility"))]
+    observers: Vec<LatLon>,
+
+    /// Height of the observers above the ground, in meters
+    #[arg(long, default_value = "1.7", requires("visibility"))]
+    observer_height: f64,
+
+    /// Height above the ground of the points to see, in meters
+    #[arg(long, default_value = "0.", requires("visibility"))]
+    target_height: f64,
+
+    /// Distance beyond which nothing is visible, in meters
+    #[arg(long, requires("visibility"))]
+    max_distance: Option<f64>,
+
+    /// Ignore the curvature of the earth in the viewshed
+    #[arg(long, requires("visibility"))]
+    flat_earth: bool,
+
+    /// Coefficient of atmospheric refraction of the viewshed
+    #[arg(
+        long,
+        default_value_t = DEFAULT_REFRACTION,
+        conflicts_with("flat_earth"),
+        requires("visibility")
+    )]
+    refraction: f64,
 }
 
-fn fetch_elevation_from_ign(lon_str: &str, lat_str: &str) -> Result<ElevationResponse> {
-    let start_url = "https://wxs.ign.fr/calcul/alti/rest/elevation.json?";
-    let end_url = "&zonly=true";
-    let full_url = format!("{}{}&{}&{}", start_url, lon_str, lat_str, end_url);
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Manage the elevation cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Fetch the elevation profile along a route and save it as CSV
+    Profile {
+        /// Points of the route as latitude,longitude, after -- if a latitude is negative
+        #[arg(required_unless_present("route"), conflicts_with("route"))]
+        points: Vec<LatLon>,
 
-    let response = get(&full_url).context("Failed to get the request")?;
+        /// Path of the route instead of the points: GPX track or route (.gpx), or GeoJSON
+        /// LineString (.geojson, .json)
+        #[arg(long)]
+        route: Option<String>,
 
-    if response.status().is_success() {
-        let elevation_data: ElevationResponse = response
-            .json()
-            .context("Failed to parse response as JSON")?;
-        Ok(elevation_data)
-    } else {
-        Err(anyhow::anyhow!(
-            "Request failed with status: {}",
-            response.status()
-        ))
+        /// Maximum distance between the points of the profile, in meters
+        #[arg(long, default_value = "10.")]
+        spacing: f64,
+
+        /// Path of the CSV profile
+        #[arg(short, long, default_value = "profile.csv")]
+        output: String,
+    },
+    /// Compute the viewshed of observers on a saved heightmap, without fetching it again
+    Viewshed {
+        /// Path of the heightmap: GeoTIFF (.tif) or ESRI ASCII Grid (.asc) with its .prj
+        input: String,
+
+        #[command(flatten)]
+        visibility: VisibilityArgs,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheAction {
+    /// Show the number of cached elevations
+    Stats,
+    /// Remove all the cached elevations
+    Clear,
+}
+
+fn run_cache(args: &Args, action: &CacheAction) -> Result<()> {
+    let path = args
+        .cache
+        .as_ref()
+        .with_context(|| format!("No cache given, use --cache or {}", CACHE_ENV))?;
+    let cache = ElevationCache::open(path)?;
+    match action {
+        CacheAction::Stats => {
+            let stats = cache.stats()?;
+            println!(
+                "{}: {} elevations, {} bytes",
+                path.display(),
+                stats.entries,
+                stats.size
+            );
+            for (dataset, entries) in stats.datasets {
+                println!("  {}: {}", dataset, entries);
+            }
+        }
+        CacheAction::Clear => {
+            let removed = cache.clear()?;
+            println!("Removed {} elevations from {}", removed, path.display());
+        }
     }
+    Ok(())
+}
+
+// Provider of the altimetry service behind the cache if any, with the name of its dataset and
+// the identifier of its data (see `IgnProvider::source`).
+fn elevation_provider(args: &Args) -> Result<(Box<dyn ElevationProvider + Sync>, String, String)> {
+    let provider = IgnProvider::new()
+        .with_endpoint(&args.endpoint)
+        .with_resource(&args.resource)
+        .with_retry(RetryPolicy {
+            max_retries: args.retries,
+            ..RetryPolicy::default()
+        })
+        .with_rate_limit((args.rate_limit > 0.).then_some(args.rate_limit));
+    let resource = provider.resource().to_string();
+    let source = provider.source();
+    let provider: Box<dyn ElevationProvider + Sync> = match &args.cache {
+        Some(path) => {
+            let cache = ElevationCache::open(path)?.with_max_entries(Some(args.cache_max_entries));
+            Box::new(CachedProvider::new(provider, cache, &source))
+        }
+        None => Box::new(provider),
+    };
+    Ok((provider, resource, source))
 }
 
-fn save_elevation_data(
+fn run_profile(
+    args: &Args,
+    points: &[LatLon],
+    route: &Option<String>,
+    spacing: f64,
     output: &str,
-    heights: &[f64],
-    positions: &[(f64, f64)],
-    resolution: f64,
 ) -> Result<()> {
-    let file = File::create(output).context("Failed to create HDF5 file")?;
-    let dataset = file
-        .new_dataset::<f64>()
-        .shape([heights.len()])
-        .create("heights")
-        .context("Failed to create 'heights' dataset")?;
-    let _ = dataset.write(&heights);
-
-    let dataset = file
-        .new_dataset::<(f64, f64)>()
-        .shape([positions.len()])
-        .create("positions")
-        .context("Failed to create 'positions' dataset")?;
-    let _ = dataset.write(&positions);
-
-    let dataset = file
-        .new_dataset::<f64>()
-        .create("resolution")
-        .context("Failed to create 'resolution' scalar")?;
-    let _ = dataset.write_scalar(&resolution);
+    let route = match route {
+        Some(path) => read_route(path)?,
+        None => points.to_vec(),
+    };
+    anyhow::ensure!(route.len() >= 2, "A route needs at least 2 points");
+    anyhow::ensure!(spacing > 0., "The spacing must be positive");
+    let mut profile = densify(&route, spacing);
+    let positions: Vec<(f64, f64)> = profile
+        .iter()
+        .map(|point| (point.position.longitude, point.position.latitude))
+        .collect();
 
+    println!("Fetching {} points from the IGN API ...", positions.len());
+    let options = FetchOptions {
+        workers: args.workers,
+        ..FetchOptions::default()
+    };
+    let (provider, _, _) = elevation_provider(args)?;
+    let pb = ProgressBar::new(positions.len().div_ceil(options.batch_size) as u64);
+    let elevations = fetch_elevations(&*provider, &positions, &options, |_, _| pb.inc(1))?;
+    pb.finish();
+    for err in &elevations.errors {
+        eprintln!(
+            "Error fetching elevation data (batch {}): {:#}",
+            err.index, err.error
+        );
+    }
+    if !elevations.is_complete() && !args.allow_partial {
+        anyhow::bail!(
+            "Incomplete elevation data, {} of {} points have no data, use --allow-partial to \
+             save the profile anyway",
+            elevations.missing_points(),
+            positions.len()
+        );
+    }
+    for (point, elevation) in profile.iter_mut().zip(elevations.heights) {
+        point.elevation = elevation;
+    }
+
+    println!("Saving the profile to {}", output);
+    save_profile_csv(output, &profile)?;
+    let summary = summarize(&profile);
+    println!("Length: {:.3} km", summary.length / 1000.);
+    println!(
+        "Elevation: {:.1} m to {:.1} m, gain {:.1} m, loss {:.1} m",
+        summary.min_elevation, summary.max_elevation, summary.gain, summary.loss
+    );
+    println!(
+        "Steepest grade: {:+.1} % up, {:+.1} % down",
+        summary.max_grade, summary.min_grade
+    );
     Ok(())
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+// Save the viewshed outputs of `data` that are asked for.
+fn save_visibility(
+    data: &ElevationGrid,
+    args: &VisibilityArgs,
+    output_options: &OutputOptions,
+) -> Result<()> {
+    let options = ViewshedOptions {
+        observer_height: args.observer_height,
+        target_height: args.target_height,
+        max_distance: args.max_distance,
+        curvature: !args.flat_earth,
+        refraction: args.refraction,
+    };
+    if let Some(path) = &args.viewshed {
+        let mask = viewshed(data, &args.observers, &options)?;
+        save_as(path, raster_format(path)?, &mask, output_options)?;
+    }
+    if let Some(path) = &args.visibility_count {
+        let count = visibility_count(data, &args.observers, &options)?;
+        save_as(path, raster_format(path)?, &count, output_options)?;
+    }
+    Ok(())
+}
 
-    println!("Calculating the positions ...");
-    let xy = calculate_xy_positions(args.latitude, args.longitude, args.size, args.resolution);
-    let map_size = xy.0.len();
+fn run_viewshed(input: &str, args: &VisibilityArgs) -> Result<()> {
+    anyhow::ensure!(
+        args.viewshed.is_some() || args.visibility_count.is_some(),
+        "Nothing to compute, use --viewshed or --visibility-count"
+    );
+    for path in [&args.viewshed, &args.visibility_count]
+        .into_iter()
+        .flatten()
+    {
+        raster_format(path)?;
+    }
+    println!("Reading the heightmap {} ...", input);
+    let data = read_raster(input)?;
+    save_visibility(&data, args, &OutputOptions::default())
+}
 
-    let positions: Vec<(f64, f64)> =
-        xy.0.iter()
-            .flat_map(|x| xy.1.iter().map(|y| (*x, *y)))
-            .collect();
+fn run_fetch(args: &Args) -> Result<()> {
+    println!("Calculating the positions ...");
+    let x_resolution = args.x_resolution.unwrap_or(args.resolution);
+    let y_resolution = args.y_resolution.unwrap_or(args.resolution);
+    let crs = if args.lambert93 {
+        Crs::lambert93()
+    } else {
+        args.crs
+    };
+    let grid = match (&args.bbox, args.latitude, args.longitude) {
+        (Some(bbox), _, _) => Grid::from_bbox_with_crs(crs, bbox, x_resolution, y_resolution),
+        (None, Some(latitude), Some(longitude)) => Grid::with_crs(
+            crs,
+            latitude,
+            longitude,
+            args.width.unwrap_or(args.size),
+            args.height.unwrap_or(args.size),
+            x_resolution,
+            y_resolution,
+        ),
+        _ => unreachable!("clap requires a center or a bounding box"),
+    };
+    let positions = grid.positions();
+    // Formats checked before fetching, so that a mistyped extension costs no request.
+    let contour_format = vector_format(&args.contours)?;
+    anyhow::ensure!(
+        args.contour_interval > 0.,
+        "The contour interval must be positive"
+    );
+    let outline_format = vector_format(&args.watershed_outline)?;
+    let rasters = [
+        &args.slope,
+        &args.slope_percent,
+        &args.aspect,
+        &args.profile_curvature,
+        &args.plan_curvature,
+        &args.visibility.viewshed,
+        &args.visibility.visibility_count,
+        &args.filled,
+        &args.flow_direction,
+        &args.flow_accumulation,
+        &args.streams,
+        &args.watershed,
+    ];
+    for path in rasters.into_iter().flatten() {
+        raster_format(path)?;
+    }
+    let format = args
+        .format
+        .or_else(|| OutputFormat::from_extension(&args.output))
+        .unwrap_or(OutputFormat::Hdf5);
+    let (dx, dy) = grid.spacing();
+    let ascii_grid = format == OutputFormat::Asc
+        || rasters
+            .into_iter()
+            .flatten()
+            .any(|path| OutputFormat::from_extension(path) == Some(OutputFormat::Asc));
+    if ascii_grid && (dx - dy).abs() > 1e-9 * dx.abs().max(dy.abs()) {
+        eprintln!(
+            "The cells are not square, so the ASCII Grids get the dx/dy header of GDAL that other \
+             tools may not read: use a projected --crs and a single --resolution for square cells"
+        );
+    }
+    for point in args.visibility.observers.iter().chain(&args.pour_point) {
+        anyhow::ensure!(
+            grid.nearest_point(point).is_some(),
+            "The point {} is outside the map",
+            point
+        );
+    }
 
-    // Loop over chunks of 'positions' and request/push their elevation in 'heights'
     println!("Fetching the data from the IGN API ...");
-    let pb = ProgressBar::new((positions.len() / BATCH_SIZE) as u64);
-    let mut heights: Vec<f64> = Vec::with_capacity(positions.len());
-    for batch_pos in positions.chunks(BATCH_SIZE) {
-        let mut lon_str = "lon=".to_string();
-        let mut lat_str = "lat=".to_string();
-        for elem in batch_pos {
-            lon_str = format!("{}{}|", lon_str, elem.0);
-            lat_str = format!("{}{}|", lat_str, elem.1);
-        }
-        if lon_str.ends_with('|') {
-            lon_str.pop(); // Remove the last character
-        }
-        if lat_str.ends_with('|') {
-            lat_str.pop(); // Remove the last character
-        }
+    let options = FetchOptions {
+        workers: args.workers,
+        ..FetchOptions::default()
+    };
+    let (provider, resource, source) = elevation_provider(args)?;
 
-        match fetch_elevation_from_ign(&lon_str, &lat_str) {
-            Ok(elevation_data) => heights.extend(elevation_data.elevations),
-            Err(err) => eprintln!("Error fetching elevation data: {}", err),
+    // Completed batches are saved to a checkpoint next to the output until all the data is saved.
+    let checkpoint_path = Checkpoint::path_for(&args.output);
+    let key = Checkpoint::key(&positions, options.batch_size, &source);
+    let mut checkpoint = if args.resume {
+        Checkpoint::resume(&checkpoint_path, &key)?
+    } else {
+        Checkpoint::create(&checkpoint_path, &key)?
+    };
+    let completed = checkpoint.completed().clone();
+    if !completed.is_empty() {
+        println!("Resuming with {} batches already fetched", completed.len());
+    }
+
+    let pb = ProgressBar::new(positions.len().div_ceil(options.batch_size) as u64);
+    pb.inc(completed.len() as u64);
+    let elevations = fetch_missing_elevations(
+        &*provider,
+        &positions,
+        &options,
+        &completed,
+        |index, result| {
+            if let Ok(heights) = result {
+                if let Err(err) = checkpoint.record(index, heights) {
+                    eprintln!("Error saving the checkpoint: {:#}", err);
+                }
+            }
+            pb.inc(1);
+        },
+    )?;
+    pb.finish();
+    if !elevations.is_complete() {
+        for err in &elevations.errors {
+            eprintln!(
+                "Error fetching elevation data (batch {}): {:#}",
+                err.index, err.error
+            );
+        }
+        eprintln!(
+            "{} of {} batches failed, {} of {} points have no data",
+            elevations.errors.len(),
+            positions.len().div_ceil(options.batch_size),
+            elevations.missing_points(),
+            positions.len()
+        );
+        if !args.allow_partial {
+            anyhow::bail!(
+                "Incomplete elevation data, use --resume to fetch the missing batches \
+                 or --allow-partial to save it anyway"
+            );
         }
-        pb.inc(1);
+    } else if elevations.missing_points() > 0 {
+        eprintln!(
+            "{} of {} points are outside the coverage of {}",
+            elevations.missing_points(),
+            positions.len(),
+            resource
+        );
     }
+    let complete = elevations.is_complete();
+    let data = ElevationGrid::new(grid, elevations.heights).with_source(&resource);
 
     println!("Saving the data to {}", args.output);
-    save_elevation_data(&args.output, &heights, &positions, args.resolution)?;
-
-    if let Some(path_image) = args.image {
-        // Calculate the min and max values for normalisation and create a u8 Gray image.
-        let min: f64 = heights.iter().fold(f64::INFINITY, |a, &b| a.min(b));
-        let max: f64 = heights.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
-        let norm_heights: Vec<u8> = heights
-            .iter()
-            .map(|h| (f64::powf(2., 8.) * (h - min) / (max - min)) as u8)
-            .collect();
-        let image = GrayImage::from_vec(map_size as u32, map_size as u32, norm_heights).unwrap();
-        let rotated_image = image::imageops::rotate270(&image);
-        let _ = rotated_image
-            .save(path_image)
-            .context("Failed to save the image")?;
+    let output_options = OutputOptions {
+        compression: args.compression,
+    };
+    save_as(&args.output, format, &data, &output_options)?;
+
+    if let (Some(path_image), Some(colormap)) = (&args.image, &args.colormap) {
+        save_color_relief(path_image, &data, &ColorRamp::open(colormap)?)?;
+    } else if let Some(path_image) = &args.image {
+        let options = ImageOptions {
+            encoding: args.image_encoding,
+            normalization: args.image_normalization,
+        };
+        save_heightmap_image(path_image, &data, &options)?;
+    }
+    if let Some(path) = &args.hillshade {
+        let options = HillshadeOptions {
+            z_factor: args.z_factor,
+            azimuth: args.azimuth,
+            altitude: args.altitude,
+            multidirectional: args.multidirectional,
+        };
+        let shade = hillshade(&data, &options);
+        match OutputFormat::from_extension(path) {
+            Some(format) => save_as(path, format, &shade, &output_options)?,
+            None => save_hillshade_image(path, &shade)?,
+        }
+    }
+    let products = [
+        (&args.slope, TerrainProduct::SlopeDegrees),
+        (&args.slope_percent, TerrainProduct::SlopePercent),
+        (&args.aspect, TerrainProduct::Aspect),
+        (&args.profile_curvature, TerrainProduct::ProfileCurvature),
+        (&args.plan_curvature, TerrainProduct::PlanCurvature),
+    ];
+    for (path, product) in products {
+        if let Some(path) = path {
+            save_as(
+                path,
+                raster_format(path)?,
+                &terrain_product(&data, product),
+                &output_options,
+            )?;
+        }
+    }
+    save_visibility(&data, &args.visibility, &output_options)?;
+    if let (Some(path), Some(format)) = (&args.contours, contour_format) {
+        let options = ContourOptions {
+            interval: args.contour_interval,
+            base: args.contour_base,
+            index_every: args.index_contours,
+            smoothing: args.contour_smoothing,
+        };
+        save_contours(path, format, &contours(&data, &options)?, &data.grid)?;
+    }
+    let hydrology = [
+        &args.filled,
+        &args.flow_direction,
+        &args.flow_accumulation,
+        &args.streams,
+        &args.watershed,
+        &args.watershed_outline,
+    ];
+    if hydrology.iter().any(|path| path.is_some()) {
+        let filled = fill_depressions(&data);
+        let save = |path: &String, data: &ElevationGrid| {
+            save_as(path, raster_format(path)?, data, &output_options)
+        };
+        if let Some(path) = &args.filled {
+            save(path, &filled)?;
+        }
+        if let Some(path) = &args.flow_direction {
+            save(path, &flow_direction(&filled, args.flow_method))?;
+        }
+        let accumulation = flow_accumulation(&filled, args.flow_method);
+        if let Some(path) = &args.flow_accumulation {
+            save(path, &accumulation)?;
+        }
+        if let Some(path) = &args.streams {
+            save(path, &streams(&accumulation, args.stream_threshold))?;
+        }
+        if let Some(pour_point) = &args.pour_point {
+            let cell = data
+                .grid
+                .nearest_point(pour_point)
+                .context("The pour point is outside the map")?;
+            // Watersheds follow the D8 directions, so the pour point goes onto a D8 stream.
+            let d8_accumulation;
+            let accumulation = match args.flow_method {
+                FlowMethod::D8 => &accumulation,
+                FlowMethod::DInfinity => {
+                    d8_accumulation = flow_accumulation(&filled, FlowMethod::D8);
+                    &d8_accumulation
+                }
+            };
+            let cell = snap_pour_point(accumulation, cell, args.snap_distance);
+            let basin = watershed(&filled, cell);
+            let polygons = outline(&basin);
+            let area: f64 = polygons.iter().map(|polygon| polygon.area).sum();
+            println!("Watershed area: {:.3} km²", area / 1e6);
+            if let Some(path) = &args.watershed {
+                save(path, &basin)?;
+            }
+            if let (Some(path), Some(format)) = (&args.watershed_outline, outline_format) {
+                save_polygons(path, format, &polygons, &data.grid)?;
+            }
+        }
+    }
+    // With --allow-partial, the checkpoint stays for --resume to fetch the failed batches.
+    if complete {
+        checkpoint.remove()?;
     }
 
     Ok(())
 }
+
+// Format of the raster output at `path`, from its extension.
+fn raster_format(path: &str) -> Result<OutputFormat> {
+    OutputFormat::from_extension(path).with_context(|| {
+        format!(
+            "Unknown raster format of {}, expected .h5, .nc, .tif, .asc or .xyz",
+            path
+        )
+    })
+}
+
+// Format of the vector output at `path`, if any, from its extension.
+fn vector_format(path: &Option<String>) -> Result<Option<VectorFormat>> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let format = VectorFormat::from_extension(path).with_context(|| {
+        format!(
+            "Unknown vector format of {}, expected .geojson, .shp or .svg",
+            path
+        )
+    })?;
+    Ok(Some(format))
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    match &args.command {
+        Some(Command::Cache { action }) => run_cache(&args, action),
+        Some(Command::Profile {
+            points,
+            route,
+            spacing,
+            output,
+        }) => run_profile(&args, points, route, *spacing, output),
+        Some(Command::Viewshed { input, visibility }) => run_viewshed(input, visibility),
+        None => run_fetch(&args),
+    }
+}
@@ -0,0 +1,63 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use anyhow::{Context, Result};
+use serde_json::json;
+
+use crate::contour::Contour;
+use crate::hydrology::Polygon;
+use crate::projection::Crs;
+
+/// Save `contours` as a GeoJSON collection of LineString features in longitude/latitude, with
+/// their `elevation` in meters and whether they are `index` contours as properties.
+pub fn save_contours_geojson(path: &str, contours: &[Contour], crs: Crs) -> Result<()> {
+    let features: Vec<_> = contours
+        .iter()
+        .map(|contour| {
+            json!({
+                "type": "Feature",
+                "geometry": {"type": "LineString", "coordinates": wgs84(&contour.points, crs)},
+                "properties": {"elevation": contour.elevation, "index": contour.index},
+            })
+        })
+        .collect();
+    save_features(path, features)
+}
+
+/// Save `polygons` as a GeoJSON collection of Polygon features in longitude/latitude, with
+/// their `area` in square meters as property.
+pub fn save_polygons_geojson(path: &str, polygons: &[Polygon], crs: Crs) -> Result<()> {
+    let features: Vec<_> = polygons
+        .iter()
+        .map(|polygon| {
+            let rings: Vec<_> = std::iter::once(&polygon.exterior)
+                .chain(&polygon.holes)
+                .map(|ring| wgs84(ring, crs))
+                .collect();
+            json!({
+                "type": "Feature",
+                "geometry": {"type": "Polygon", "coordinates": rings},
+                "properties": {"area": polygon.area},
+            })
+        })
+        .collect();
+    save_features(path, features)
+}
+
+// (Longitude, latitude) coordinates of points in `crs`.
+fn wgs84(points: &[(f64, f64)], crs: Crs) -> Vec<[f64; 2]> {
+    points
+        .iter()
+        .map(|&(x, y)| {
+            let (lon, lat) = crs.to_wgs84(x, y);
+            [lon, lat]
+        })
+        .collect()
+}
+
+fn save_features(path: &str, features: Vec<serde_json::Value>) -> Result<()> {
+    let collection = json!({"type": "FeatureCollection", "features": features});
+    let file = File::create(path).with_context(|| format!("Failed to create {}", path))?;
+    serde_json::to_writer(BufWriter::new(file), &collection)
+        .with_context(|| format!("Failed to write {}", path))
+}
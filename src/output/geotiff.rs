@@ -0,0 +1,168 @@
+use anyhow::Result;
+
+use super::tiff::{
+    float32_image, float32_tiled_image, write_tiff, Compression, TiffImage, Value,
+    NEW_SUBFILE_TYPE, SUBFILE_REDUCED_IMAGE,
+};
+use super::NODATA;
+use crate::grid::{ElevationGrid, Grid, Quantity};
+use crate::projection::Crs;
+
+const MODEL_PIXEL_SCALE: u16 = 33550;
+const MODEL_TIEPOINT: u16 = 33922;
+const GEO_KEY_DIRECTORY: u16 = 34735;
+const GDAL_METADATA: u16 = 42112;
+const GDAL_NODATA: u16 = 42113;
+
+const GT_MODEL_TYPE: u16 = 1024;
+const GT_RASTER_TYPE: u16 = 1025;
+const GEOGRAPHIC_TYPE: u16 = 2048;
+const GEOG_ANGULAR_UNITS: u16 = 2054;
+const PROJECTED_CS_TYPE: u16 = 3072;
+const PROJ_LINEAR_UNITS: u16 = 3076;
+
+const MODEL_TYPE_PROJECTED: u16 = 1;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+const RASTER_PIXEL_IS_AREA: u16 = 1;
+const ANGULAR_UNIT_DEGREE: u16 = 9102;
+const LINEAR_UNIT_METER: u16 = 9001;
+
+/// Add the georeferencing of `grid` to a north-up image of it: each pixel is the cell
+/// centered on a point of the grid.
+pub(crate) fn georeference(image: &mut TiffImage, grid: &Grid) {
+    let (dx, dy) = grid.spacing();
+    let west = grid.x.first().copied().unwrap_or_default() - 0.5 * dx;
+    let north = grid.y.last().copied().unwrap_or_default() + 0.5 * dy;
+    image.set(MODEL_PIXEL_SCALE, Value::Double(vec![dx, dy, 0.]));
+    image.set(
+        MODEL_TIEPOINT,
+        Value::Double(vec![0., 0., 0., west, north, 0.]),
+    );
+
+    let mut keys = vec![(GT_RASTER_TYPE, RASTER_PIXEL_IS_AREA)];
+    match grid.crs {
+        Crs::Wgs84 => keys.extend([
+            (GT_MODEL_TYPE, MODEL_TYPE_GEOGRAPHIC),
+            (GEOGRAPHIC_TYPE, grid.crs.epsg() as u16),
+            (GEOG_ANGULAR_UNITS, ANGULAR_UNIT_DEGREE),
+        ]),
+        Crs::Projected { epsg, .. } => keys.extend([
+            (GT_MODEL_TYPE, MODEL_TYPE_PROJECTED),
+            (PROJECTED_CS_TYPE, epsg as u16),
+            (PROJ_LINEAR_UNITS, LINEAR_UNIT_METER),
+        ]),
+    }
+    keys.sort();
+    // Directory header: version 1.1.0 and number of keys, then (key, location, count, value).
+    let mut directory = vec![1, 1, 0, keys.len() as u16];
+    for (key, value) in keys {
+        directory.extend([key, 0, 1, value]);
+    }
+    image.set(GEO_KEY_DIRECTORY, Value::Short(directory));
+}
+
+/// Set the GDAL no-data tag of an image.
+pub(crate) fn set_nodata(image: &mut TiffImage, nodata: f64) {
+    image.set(GDAL_NODATA, Value::Ascii(nodata.to_string()));
+}
+
+/// Describe the band of an image as `quantity` in the GDAL metadata tag, so that a derived
+/// grid such as a hillshade is not taken for heights.
+pub(crate) fn set_quantity(image: &mut TiffImage, quantity: &Quantity) {
+    let mut metadata = format!(
+        "<GDALMetadata><Item name=\"DESCRIPTION\" sample=\"0\" role=\"description\">{}</Item>\
+         <Item name=\"UNITTYPE\" sample=\"0\" role=\"unittype\">{}</Item>\
+         <Item name=\"long_name\" sample=\"0\">{}</Item>",
+        quantity.name, quantity.units, quantity.long_name
+    );
+    if let Some(standard_name) = quantity.standard_name {
+        metadata += &format!(
+            "<Item name=\"standard_name\" sample=\"0\">{}</Item>",
+            standard_name
+        );
+    }
+    metadata += "</GDALMetadata>";
+    image.set(GDAL_METADATA, Value::Ascii(metadata));
+}
+
+/// North-up Float32 values of `data`, with [`NODATA`] for the missing heights.
+pub(crate) fn float32_rows(data: &ElevationGrid) -> Vec<f32> {
+    data.rows_north_up()
+        .into_iter()
+        .map(|h| if h.is_nan() { NODATA } else { h } as f32)
+        .collect()
+}
+
+/// Size in pixels of the tiles of a cloud-optimized GeoTIFF.
+pub const COG_TILE_SIZE: usize = 256;
+
+/// Save `data` as a single-band Float32 GeoTIFF in the CRS of its grid.
+pub fn save_geotiff(output: &str, data: &ElevationGrid, compression: Compression) -> Result<()> {
+    let (nx, ny) = data.grid.shape();
+    let mut image = float32_image(&float32_rows(data), nx, ny, compression)?;
+    georeference(&mut image, &data.grid);
+    set_nodata(&mut image, NODATA);
+    set_quantity(&mut image, &data.quantity);
+    write_tiff(output, &[image])
+}
+
+/// Save `data` as a cloud-optimized GeoTIFF: tiled, with overviews halving the resolution
+/// down to a single tile, and all the IFDs at the start of the file.
+pub fn save_cog(output: &str, data: &ElevationGrid, compression: Compression) -> Result<()> {
+    let (mut width, mut height) = data.grid.shape();
+    let mut values = float32_rows(data);
+    let mut image = float32_tiled_image(
+        &values,
+        width,
+        height,
+        COG_TILE_SIZE,
+        NODATA as f32,
+        compression,
+    )?;
+    georeference(&mut image, &data.grid);
+    set_nodata(&mut image, NODATA);
+    set_quantity(&mut image, &data.quantity);
+
+    let mut images = vec![image];
+    while width > COG_TILE_SIZE || height > COG_TILE_SIZE {
+        (values, width, height) = downsample(&values, width, height);
+        let mut overview = float32_tiled_image(
+            &values,
+            width,
+            height,
+            COG_TILE_SIZE,
+            NODATA as f32,
+            compression,
+        )?;
+        overview.set(NEW_SUBFILE_TYPE, Value::Long(vec![SUBFILE_REDUCED_IMAGE]));
+        set_nodata(&mut overview, NODATA);
+        images.push(overview);
+    }
+    write_tiff(output, &images)
+}
+
+// Halve the resolution of an image, averaging blocks of 2 x 2 pixels without the no-data ones.
+fn downsample(values: &[f32], width: usize, height: usize) -> (Vec<f32>, usize, usize) {
+    let (half_width, half_height) = (width.div_ceil(2), height.div_ceil(2));
+    let mut reduced = Vec::with_capacity(half_width * half_height);
+    for row in 0..half_height {
+        for col in 0..half_width {
+            let (mut sum, mut count) = (0., 0);
+            for r in (2 * row)..(2 * row + 2).min(height) {
+                for c in (2 * col)..(2 * col + 2).min(width) {
+                    let value = values[r * width + c];
+                    if value != NODATA as f32 {
+                        sum += value as f64;
+                        count += 1;
+                    }
+                }
+            }
+            reduced.push(if count > 0 {
+                (sum / count as f64) as f32
+            } else {
+                NODATA as f32
+            });
+        }
+    }
+    (reduced, half_width, half_height)
+}
@@ -0,0 +1,95 @@
+use anyhow::{Context, Result};
+use hdf5::types::VarLenUnicode;
+use hdf5::{File, Location};
+
+use super::{utc_timestamp, NODATA};
+use crate::grid::ElevationGrid;
+use crate::projection::Crs;
+
+// Side in points of the chunks of the elevation dataset.
+const CHUNK_SIZE: usize = 256;
+const DEFLATE_LEVEL: u8 = 6;
+
+/// Save `data` as an HDF5 file.
+///
+/// The heights are the 2D dataset `elevation[rows][cols]` in meters, north-up, with [`NODATA`]
+/// for the missing heights, or the dataset named after the quantity of a derived grid. Its axes
+/// are the `lat`/`lon` datasets for a longitude/latitude grid, `y`/`x` for a projected one. The
+/// file attributes give the CRS, the source dataset, the requested center, the creation date
+/// and the version of the tool.
+pub fn save_elevation_data(output: &str, data: &ElevationGrid) -> Result<()> {
+    let grid = &data.grid;
+    let (nx, ny) = grid.shape();
+    let (x_name, y_name, x_units, y_units) = match grid.crs {
+        Crs::Wgs84 => ("lon", "lat", "degrees_east", "degrees_north"),
+        Crs::Projected { .. } => ("x", "y", "m", "m"),
+    };
+
+    let file = File::create(output).context("Failed to create HDF5 file")?;
+    let heights: Vec<f64> = data
+        .rows_north_up()
+        .into_iter()
+        .map(|h| if h.is_nan() { NODATA } else { h })
+        .collect();
+    let dataset = file
+        .new_dataset::<f64>()
+        .shape([ny, nx])
+        .chunk([ny.clamp(1, CHUNK_SIZE), nx.clamp(1, CHUNK_SIZE)])
+        .deflate(DEFLATE_LEVEL)
+        .fill_value(NODATA)
+        .create(data.quantity.name)
+        .with_context(|| format!("Failed to create '{}' dataset", data.quantity.name))?;
+    dataset.write_raw(&heights)?;
+    write_str_attr(&dataset, "units", data.quantity.units)?;
+    write_str_attr(&dataset, "long_name", data.quantity.long_name)?;
+    write_f64_attr(&dataset, "nodata", &[NODATA])?;
+    write_str_attr(&dataset, "dimensions", &format!("{} {}", y_name, x_name))?;
+
+    // Axes of the grid in the coordinates of its CRS, y from north to south like the rows.
+    let y: Vec<f64> = grid.y.iter().rev().copied().collect();
+    for (name, values, units) in [(x_name, &grid.x, x_units), (y_name, &y, y_units)] {
+        let dataset = file
+            .new_dataset::<f64>()
+            .shape([values.len()])
+            .create(name)
+            .with_context(|| format!("Failed to create '{}' dataset", name))?;
+        dataset.write(values)?;
+        write_str_attr(&dataset, "units", units)?;
+    }
+
+    let (longitude, latitude) = grid.center;
+    write_str_attr(&file, "crs", &grid.crs.to_string())?;
+    write_f64_attr(&file, "resolution", &[grid.x_resolution, grid.y_resolution])?;
+    write_f64_attr(&file, "center", &[longitude, latitude])?;
+    if let Some(source) = &data.source {
+        write_str_attr(&file, "source", source)?;
+    }
+    write_str_attr(&file, "date", &utc_timestamp())?;
+    write_str_attr(
+        &file,
+        "tool",
+        concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")),
+    )?;
+
+    Ok(())
+}
+
+fn write_str_attr(location: &Location, name: &str, value: &str) -> Result<()> {
+    let value: VarLenUnicode = value.parse()?;
+    location
+        .new_attr::<VarLenUnicode>()
+        .create(name)
+        .with_context(|| format!("Failed to create '{}' attribute", name))?
+        .write_scalar(&value)?;
+    Ok(())
+}
+
+fn write_f64_attr(location: &Location, name: &str, values: &[f64]) -> Result<()> {
+    location
+        .new_attr::<f64>()
+        .shape([values.len()])
+        .create(name)
+        .with_context(|| format!("Failed to create '{}' attribute", name))?
+        .write(values)?;
+    Ok(())
+}
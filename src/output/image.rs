@@ -0,0 +1,301 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use image::{ImageBuffer, Luma, RgbImage};
+use serde::{Deserialize, Serialize};
+
+use super::geotiff::{georeference, set_nodata};
+use super::tiff::{float32_image, write_tiff, Compression};
+use super::NODATA;
+use crate::grid::ElevationGrid;
+
+/// Pixel encoding of a heightmap image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageEncoding {
+    /// 16-bit grayscale, 0 for the missing heights.
+    Gray16,
+    /// 32-bit float TIFF, [`NODATA`] for the missing heights.
+    Float32,
+    /// Mapbox Terrain-RGB: `-10000 + (R * 65536 + G * 256 + B) * 0.1`, black for the missing
+    /// heights.
+    #[serde(rename = "terrain-rgb")]
+    TerrainRgb,
+    /// Terrarium: `R * 256 + G + B / 256 - 32768`, black for the missing heights.
+    Terrarium,
+}
+
+impl ImageEncoding {
+    /// Encoding for the extension of `path`: Float32 for a TIFF, Gray16 otherwise.
+    pub fn from_extension(path: &str) -> Self {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("tif" | "tiff") => ImageEncoding::Float32,
+            _ => ImageEncoding::Gray16,
+        }
+    }
+}
+
+impl FromStr for ImageEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "gray16" => Ok(ImageEncoding::Gray16),
+            "float32" => Ok(ImageEncoding::Float32),
+            "terrain-rgb" | "terrainrgb" => Ok(ImageEncoding::TerrainRgb),
+            "terrarium" => Ok(ImageEncoding::Terrarium),
+            _ => anyhow::bail!(
+                "Unknown image encoding '{}', expected gray16, float32, terrain-rgb or terrarium",
+                s
+            ),
+        }
+    }
+}
+
+/// Mapping of the heights to the pixel values of an image.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Normalization {
+    /// From the lowest to the highest height of the map.
+    #[default]
+    MinMax,
+    /// From `min` to `max` meters, the heights outside being clamped.
+    Range { min: f64, max: f64 },
+    /// Heights in meters times the scale, e.g. 10 for decimeters, from a whole number of steps
+    /// below the lowest height for the gray16 encoding.
+    Scale(f64),
+}
+
+impl FromStr for Normalization {
+    type Err = anyhow::Error;
+
+    /// Parse `minmax`, `range:<min>,<max>` or `scale:<factor>`.
+    fn from_str(s: &str) -> Result<Self> {
+        let (mode, parameters) = s.split_once(':').unwrap_or((s, ""));
+        match mode.to_ascii_lowercase().as_str() {
+            "minmax" => Ok(Normalization::MinMax),
+            "range" => {
+                let (min, max) = parameters
+                    .split_once(',')
+                    .context("Expected range:<min>,<max>")?;
+                let (min, max): (f64, f64) = (
+                    min.trim().parse().context("Invalid minimum height")?,
+                    max.trim().parse().context("Invalid maximum height")?,
+                );
+                anyhow::ensure!(min < max, "The minimum height must be below the maximum");
+                Ok(Normalization::Range { min, max })
+            }
+            "scale" => {
+                let scale: f64 = parameters
+                    .trim()
+                    .parse()
+                    .context("Expected scale:<factor>")?;
+                anyhow::ensure!(scale > 0., "The scale must be positive");
+                Ok(Normalization::Scale(scale))
+            }
+            _ => anyhow::bail!(
+                "Unknown normalization '{}', expected minmax, range:<min>,<max> or scale:<factor>",
+                s
+            ),
+        }
+    }
+}
+
+/// Options of a heightmap image.
+#[derive(Debug, Clone, Default)]
+pub struct ImageOptions {
+    /// Encoding of the pixels, guessed from the extension of the image by default.
+    pub encoding: Option<ImageEncoding>,
+    /// Mapping of the heights, ignored by the RGB encodings whose mapping is fixed.
+    pub normalization: Normalization,
+}
+
+/// Description of a heightmap image, saved next to it to turn its pixels back into heights:
+/// `height = offset + value * scale` for every pixel other than `nodata`, the value of an RGB
+/// pixel being `R * 65536 + G * 256 + B`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageSidecar {
+    pub encoding: ImageEncoding,
+    /// `minmax`, `range` or `scale`, or `fixed` for the RGB encodings.
+    pub normalization: String,
+    /// Height in meters of the pixel value 0.
+    pub offset: f64,
+    /// Meters per unit of pixel value.
+    pub scale: f64,
+    /// Pixel value of the missing heights.
+    pub nodata: f64,
+    pub width: usize,
+    pub height: usize,
+    pub crs: String,
+    /// West, south, east and north edges of the image in the coordinates of the CRS.
+    pub bounds: [f64; 4],
+    /// Lowest and highest heights of the map, in meters.
+    pub min_height: f64,
+    pub max_height: f64,
+}
+
+impl ImageSidecar {
+    /// Path of the sidecar of an image.
+    pub fn path_for(image: &str) -> String {
+        format!("{}.json", image)
+    }
+}
+
+/// Save `data` as a north-up image and its [`ImageSidecar`].
+pub fn save_heightmap_image(
+    path_image: &str,
+    data: &ElevationGrid,
+    options: &ImageOptions,
+) -> Result<()> {
+    let (nx, ny) = data.grid.shape();
+    let heights = data.rows_north_up();
+    let encoding = options
+        .encoding
+        .unwrap_or_else(|| ImageEncoding::from_extension(path_image));
+
+    let valid = heights.iter().filter(|h| !h.is_nan());
+    let min_height = valid.clone().fold(f64::INFINITY, |a, &b| a.min(b));
+    let max_height = valid.fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+    let normalization = match encoding {
+        ImageEncoding::Gray16 | ImageEncoding::Float32 => Some(options.normalization),
+        ImageEncoding::TerrainRgb | ImageEncoding::Terrarium => None,
+    };
+    let (mode, min, max) = match normalization {
+        Some(Normalization::MinMax) => ("minmax", min_height, max_height),
+        Some(Normalization::Range { min, max }) => ("range", min, max),
+        Some(Normalization::Scale(_)) => ("scale", 0., 0.),
+        None => ("fixed", 0., 0.),
+    };
+    // Largest pixel value of the normalised range, 0 being kept for the missing heights.
+    let levels = match encoding {
+        ImageEncoding::Gray16 => u16::MAX as f64 - 1.,
+        _ => 1.,
+    };
+    let (offset, scale) = match normalization {
+        None if encoding == ImageEncoding::TerrainRgb => (-10000., 0.1),
+        None => (-32768., 1. / 256.),
+        // Unsigned pixels start below the lowest height, which may be negative.
+        Some(Normalization::Scale(factor)) if encoding == ImageEncoding::Gray16 => {
+            let step = 1. / factor;
+            let offset = if min_height.is_finite() {
+                ((min_height / step).floor() - 1.) * step
+            } else {
+                -step
+            };
+            anyhow::ensure!(
+                !max_height.is_finite() || (max_height - offset) / step <= u16::MAX as f64,
+                "The heights span more than {} steps of 1/{} m, too many for 16 bits",
+                u16::MAX - 1,
+                factor
+            );
+            (offset, step)
+        }
+        Some(Normalization::Scale(factor)) => (0., 1. / factor),
+        _ if max > min => {
+            let step = (max - min) / levels;
+            match encoding {
+                ImageEncoding::Gray16 => (min - step, step),
+                _ => (min, step),
+            }
+        }
+        // A flat or empty map.
+        _ => match encoding {
+            ImageEncoding::Gray16 => (min.min(0.) - 1., 1.),
+            _ => (min.min(0.), 1.),
+        },
+    };
+    // Only a fixed range clamps the heights.
+    let (low, high) = match normalization {
+        Some(Normalization::Range { min, max }) => (min, max),
+        _ => (f64::NEG_INFINITY, f64::INFINITY),
+    };
+    let value = |h: f64| (h.clamp(low, high) - offset) / scale;
+
+    let nodata = match encoding {
+        ImageEncoding::Gray16 => {
+            let pixels: Vec<u16> = heights
+                .iter()
+                .map(|&h| {
+                    if h.is_nan() {
+                        0
+                    } else {
+                        value(h).round().clamp(1., u16::MAX as f64) as u16
+                    }
+                })
+                .collect();
+            let image = ImageBuffer::<Luma<u16>, _>::from_vec(nx as u32, ny as u32, pixels)
+                .context("Heights do not match the map size")?;
+            image.save(path_image).context("Failed to save the image")?;
+            0.
+        }
+        ImageEncoding::Float32 => {
+            let pixels: Vec<f32> = heights
+                .iter()
+                .map(|&h| {
+                    if h.is_nan() {
+                        NODATA as f32
+                    } else {
+                        value(h) as f32
+                    }
+                })
+                .collect();
+            let mut image = float32_image(&pixels, nx, ny, Compression::None)?;
+            georeference(&mut image, &data.grid);
+            set_nodata(&mut image, NODATA);
+            write_tiff(path_image, &[image])?;
+            NODATA
+        }
+        ImageEncoding::TerrainRgb | ImageEncoding::Terrarium => {
+            let pixels: Vec<u8> = heights
+                .iter()
+                .flat_map(|&h| {
+                    let value = if h.is_nan() {
+                        0
+                    } else {
+                        value(h).round().clamp(1., 16777215.) as u32
+                    };
+                    let [_, r, g, b] = value.to_be_bytes();
+                    [r, g, b]
+                })
+                .collect();
+            let image = RgbImage::from_vec(nx as u32, ny as u32, pixels)
+                .context("Heights do not match the map size")?;
+            image.save(path_image).context("Failed to save the image")?;
+            0.
+        }
+    };
+
+    let (dx, dy) = data.grid.spacing();
+    let first = |axis: &[f64]| axis.first().copied().unwrap_or_default();
+    let last = |axis: &[f64]| axis.last().copied().unwrap_or_default();
+    let sidecar = ImageSidecar {
+        encoding,
+        normalization: mode.to_string(),
+        offset,
+        scale,
+        nodata,
+        width: nx,
+        height: ny,
+        crs: data.grid.crs.to_string(),
+        bounds: [
+            first(&data.grid.x) - 0.5 * dx,
+            first(&data.grid.y) - 0.5 * dy,
+            last(&data.grid.x) + 0.5 * dx,
+            last(&data.grid.y) + 0.5 * dy,
+        ],
+        min_height,
+        max_height,
+    };
+    let path = ImageSidecar::path_for(path_image);
+    let file = File::create(&path).with_context(|| format!("Failed to create {}", path))?;
+    serde_json::to_writer_pretty(BufWriter::new(file), &sidecar)
+        .context("Failed to write the image sidecar")?;
+
+    Ok(())
+}
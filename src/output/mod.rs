@@ -0,0 +1,209 @@
+//! Writers of the fetched elevation grids.
+
+use std::path::Path;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use anyhow::Result;
+
+use crate::contour::Contour;
+use crate::grid::{ElevationGrid, Grid};
+use crate::hydrology::Polygon;
+
+mod geojson;
+mod geotiff;
+mod hdf5;
+mod image;
+mod netcdf;
+mod relief;
+mod shapefile;
+mod svg;
+mod text;
+mod tiff;
+
+pub use self::geojson::{save_contours_geojson, save_polygons_geojson};
+pub use self::geotiff::{save_cog, save_geotiff, COG_TILE_SIZE};
+pub use self::hdf5::save_elevation_data;
+pub use self::image::{
+    save_heightmap_image, ImageEncoding, ImageOptions, ImageSidecar, Normalization,
+};
+pub use self::netcdf::save_netcdf;
+pub use self::relief::{save_color_relief, save_hillshade_image};
+pub use self::shapefile::{save_contours_shapefile, save_polygons_shapefile};
+pub use self::svg::{save_contours_svg, save_polygons_svg};
+pub use self::text::{save_ascii_grid, save_xyz};
+pub use self::tiff::Compression;
+
+/// Value written for the points without data in the formats that do not support NaN.
+pub const NODATA: f64 = -99999.;
+
+/// File format of an elevation grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Hdf5,
+    GeoTiff,
+    /// Cloud-optimized GeoTIFF.
+    Cog,
+    /// ESRI ASCII Grid.
+    Asc,
+    /// `x y z` text lines.
+    Xyz,
+    /// NetCDF with the CF conventions.
+    NetCdf,
+}
+
+/// Options of the output formats.
+#[derive(Debug, Clone, Default)]
+pub struct OutputOptions {
+    /// Compression of the GeoTIFF data, by default none for GeoTIFF and DEFLATE for COG.
+    pub compression: Option<Compression>,
+}
+
+impl OutputFormat {
+    /// Names accepted by [`OutputFormat::from_str`].
+    pub const NAMES: &'static [&'static str] = &["hdf5", "netcdf", "geotiff", "cog", "asc", "xyz"];
+
+    /// Format matching the extension of `path`, if any.
+    pub fn from_extension(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "h5" | "hdf5" | "he5" => Some(OutputFormat::Hdf5),
+            "tif" | "tiff" => Some(OutputFormat::GeoTiff),
+            "nc" => Some(OutputFormat::NetCdf),
+            "asc" => Some(OutputFormat::Asc),
+            "xyz" => Some(OutputFormat::Xyz),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "hdf5" | "h5" => Ok(OutputFormat::Hdf5),
+            "geotiff" | "tiff" | "tif" => Ok(OutputFormat::GeoTiff),
+            "netcdf" | "nc" => Ok(OutputFormat::NetCdf),
+            "cog" => Ok(OutputFormat::Cog),
+            "asc" | "aaigrid" => Ok(OutputFormat::Asc),
+            "xyz" => Ok(OutputFormat::Xyz),
+            _ => anyhow::bail!(
+                "Unknown format '{}', expected one of: {}",
+                s,
+                Self::NAMES.join(", ")
+            ),
+        }
+    }
+}
+
+/// Save `data` to `output` in the given format.
+pub fn save_as(
+    output: &str,
+    format: OutputFormat,
+    data: &ElevationGrid,
+    options: &OutputOptions,
+) -> Result<()> {
+    match format {
+        OutputFormat::Hdf5 => save_elevation_data(output, data),
+        OutputFormat::NetCdf => save_netcdf(output, data),
+        OutputFormat::GeoTiff => {
+            save_geotiff(output, data, options.compression.unwrap_or_default())
+        }
+        OutputFormat::Cog => save_cog(
+            output,
+            data,
+            options.compression.unwrap_or(Compression::Deflate),
+        ),
+        OutputFormat::Asc => save_ascii_grid(output, data),
+        OutputFormat::Xyz => save_xyz(output, data),
+    }
+}
+
+/// File format of vector data, such as contour lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorFormat {
+    GeoJson,
+    /// ESRI Shapefile.
+    Shapefile,
+    Svg,
+}
+
+impl VectorFormat {
+    /// Format matching the extension of `path`, if any.
+    pub fn from_extension(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "geojson" | "json" => Some(VectorFormat::GeoJson),
+            "shp" => Some(VectorFormat::Shapefile),
+            "svg" => Some(VectorFormat::Svg),
+            _ => None,
+        }
+    }
+}
+
+/// Save the `contours` of a map on `grid` to `path` in the given format.
+pub fn save_contours(
+    path: &str,
+    format: VectorFormat,
+    contours: &[Contour],
+    grid: &Grid,
+) -> Result<()> {
+    match format {
+        VectorFormat::GeoJson => save_contours_geojson(path, contours, grid.crs),
+        VectorFormat::Shapefile => save_contours_shapefile(path, contours, grid.crs),
+        VectorFormat::Svg => save_contours_svg(path, contours, grid),
+    }
+}
+
+/// Save the `polygons` of a map on `grid` to `path` in the given format.
+pub fn save_polygons(
+    path: &str,
+    format: VectorFormat,
+    polygons: &[Polygon],
+    grid: &Grid,
+) -> Result<()> {
+    match format {
+        VectorFormat::GeoJson => save_polygons_geojson(path, polygons, grid.crs),
+        VectorFormat::Shapefile => save_polygons_shapefile(path, polygons, grid.crs),
+        VectorFormat::Svg => save_polygons_svg(path, polygons, grid),
+    }
+}
+
+/// Current UTC date and time as ISO 8601, e.g. `2024-05-01T12:00:00Z`.
+pub(crate) fn utc_timestamp() -> String {
+    let ((year, month, day), time) = utc_now();
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+// Current UTC (year, month, day) and seconds since midnight.
+pub(crate) fn utc_now() -> ((i64, i64, i64), u64) {
+    let seconds = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (days, time) = (seconds / 86400, seconds % 86400);
+    // Civil date of a number of days since 1970-01-01, in 400-year eras starting in March.
+    let days = days as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    ((year, month, day), time)
+}
@@ -0,0 +1,529 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use anyhow::{Context, Result};
+
+use super::{utc_timestamp, NODATA};
+use crate::grid::ElevationGrid;
+use crate::projection::{Crs, Projection};
+
+// Tags and types of the classic NetCDF format.
+const NC_DIMENSION: u32 = 0x0A;
+const NC_VARIABLE: u32 = 0x0B;
+const NC_ATTRIBUTE: u32 = 0x0C;
+const NC_CHAR: u32 = 2;
+const NC_INT: u32 = 4;
+const NC_FLOAT: u32 = 5;
+const NC_DOUBLE: u32 = 6;
+
+enum Values {
+    Text(String),
+    Int(Vec<i32>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+}
+
+impl Values {
+    fn nc_type(&self) -> u32 {
+        match self {
+            Values::Text(_) => NC_CHAR,
+            Values::Int(_) => NC_INT,
+            Values::Float(_) => NC_FLOAT,
+            Values::Double(_) => NC_DOUBLE,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Values::Text(text) => text.len(),
+            Values::Int(values) => values.len(),
+            Values::Float(values) => values.len(),
+            Values::Double(values) => values.len(),
+        }
+    }
+
+    // Big-endian bytes, padded to a multiple of 4.
+    fn bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = match self {
+            Values::Text(text) => text.as_bytes().to_vec(),
+            Values::Int(values) => values.iter().flat_map(|v| v.to_be_bytes()).collect(),
+            Values::Float(values) => values.iter().flat_map(|v| v.to_be_bytes()).collect(),
+            Values::Double(values) => values.iter().flat_map(|v| v.to_be_bytes()).collect(),
+        };
+        bytes.resize(bytes.len().next_multiple_of(4), 0);
+        bytes
+    }
+}
+
+struct Variable {
+    name: &'static str,
+    dimensions: Vec<u32>,
+    attributes: Vec<(&'static str, Values)>,
+    data: Values,
+}
+
+fn text(value: &str) -> Values {
+    Values::Text(value.to_string())
+}
+
+/// Save `data` as a NetCDF file following the CF conventions.
+///
+/// The heights are the Float32 variable `elevation(y, x)` (`lat`/`lon` for a longitude/latitude
+/// grid), north-up, with [`NODATA`] as fill value. A derived grid is the variable named after
+/// its quantity. Its CRS is described by the `crs` grid mapping variable, with its CF parameters
+/// and its WKT. The file uses the 64-bit offset variant of the classic format, read by netCDF-C,
+/// xarray, CDO and GDAL.
+pub fn save_netcdf(output: &str, data: &ElevationGrid) -> Result<()> {
+    let grid = &data.grid;
+    let (nx, ny) = grid.shape();
+    let ((x_name, x_attributes), (y_name, y_attributes)) = match grid.crs {
+        Crs::Wgs84 => (
+            (
+                "lon",
+                axis_attributes("longitude", "longitude", "degrees_east"),
+            ),
+            (
+                "lat",
+                axis_attributes("latitude", "latitude", "degrees_north"),
+            ),
+        ),
+        Crs::Projected { .. } => (
+            (
+                "x",
+                axis_attributes("projection_x_coordinate", "easting", "m"),
+            ),
+            (
+                "y",
+                axis_attributes("projection_y_coordinate", "northing", "m"),
+            ),
+        ),
+    };
+    let dimensions = [(y_name, ny), (x_name, nx)];
+
+    let heights = data
+        .rows_north_up()
+        .into_iter()
+        .map(|h| if h.is_nan() { NODATA } else { h } as f32)
+        .collect();
+    let quantity = &data.quantity;
+    let mut attributes = Vec::new();
+    if let Some(standard_name) = quantity.standard_name {
+        attributes.push(("standard_name", text(standard_name)));
+    }
+    attributes.extend([
+        ("long_name", text(quantity.long_name)),
+        ("units", text(quantity.units)),
+        ("_FillValue", Values::Float(vec![NODATA as f32])),
+        ("grid_mapping", text("crs")),
+    ]);
+    let variables = vec![
+        Variable {
+            name: "crs",
+            dimensions: vec![],
+            attributes: grid_mapping_attributes(&grid.crs),
+            data: Values::Int(vec![0]),
+        },
+        Variable {
+            name: y_name,
+            dimensions: vec![0],
+            attributes: y_attributes,
+            data: Values::Double(grid.y.iter().rev().copied().collect()),
+        },
+        Variable {
+            name: x_name,
+            dimensions: vec![1],
+            attributes: x_attributes,
+            data: Values::Double(grid.x.clone()),
+        },
+        Variable {
+            name: data.quantity.name,
+            dimensions: vec![0, 1],
+            attributes,
+            data: Values::Float(heights),
+        },
+    ];
+
+    let mut attributes = vec![
+        ("Conventions", text("CF-1.8")),
+        ("title", text(data.quantity.long_name)),
+    ];
+    if let Some(source) = &data.source {
+        attributes.push(("source", text(source)));
+    }
+    attributes.push((
+        "history",
+        text(&format!(
+            "{} created by {} {}",
+            utc_timestamp(),
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        )),
+    ));
+    let (longitude, latitude) = grid.center;
+    attributes.push(("center", Values::Double(vec![longitude, latitude])));
+
+    // The header size does not depend on the data offsets, so it is laid out once to find them.
+    let blocks: Vec<Vec<u8>> = variables.iter().map(|v| v.data.bytes()).collect();
+    let mut sections: Vec<(usize, u64)> = blocks.iter().map(|block| (block.len(), 0)).collect();
+    let mut offset = header(&dimensions, &attributes, &variables, &sections).len() as u64;
+    for (size, begin) in &mut sections {
+        *begin = offset;
+        offset += *size as u64;
+    }
+
+    let file = File::create(output).with_context(|| format!("Failed to create {}", output))?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(&header(&dimensions, &attributes, &variables, &sections))?;
+    for block in &blocks {
+        writer.write_all(block)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn axis_attributes(
+    standard_name: &str,
+    long_name: &str,
+    units: &str,
+) -> Vec<(&'static str, Values)> {
+    vec![
+        ("standard_name", text(standard_name)),
+        ("long_name", text(long_name)),
+        ("units", text(units)),
+    ]
+}
+
+// CF grid mapping of a CRS, with its WKT for the tools that prefer it.
+fn grid_mapping_attributes(crs: &Crs) -> Vec<(&'static str, Values)> {
+    let mut attributes = match crs {
+        Crs::Wgs84 => vec![("grid_mapping_name", text("latitude_longitude"))],
+        Crs::Projected {
+            projection: Projection::LambertConformalConic(lcc),
+            ..
+        } => vec![
+            ("grid_mapping_name", text("lambert_conformal_conic")),
+            (
+                "standard_parallel",
+                Values::Double(vec![lcc.lat1, lcc.lat2]),
+            ),
+            (
+                "longitude_of_central_meridian",
+                Values::Double(vec![lcc.lon0]),
+            ),
+            (
+                "latitude_of_projection_origin",
+                Values::Double(vec![lcc.lat0]),
+            ),
+            ("false_easting", Values::Double(vec![lcc.false_easting])),
+            ("false_northing", Values::Double(vec![lcc.false_northing])),
+        ],
+        Crs::Projected {
+            projection: Projection::TransverseMercator(tm),
+            ..
+        } => vec![
+            ("grid_mapping_name", text("transverse_mercator")),
+            (
+                "scale_factor_at_central_meridian",
+                Values::Double(vec![tm.k0]),
+            ),
+            (
+                "longitude_of_central_meridian",
+                Values::Double(vec![tm.lon0]),
+            ),
+            ("latitude_of_projection_origin", Values::Double(vec![0.])),
+            ("false_easting", Values::Double(vec![tm.false_easting])),
+            ("false_northing", Values::Double(vec![tm.false_northing])),
+        ],
+    };
+    let ellipsoid = crs.ellipsoid();
+    let wkt = crs.wkt();
+    attributes.extend([
+        ("semi_major_axis", Values::Double(vec![ellipsoid.a])),
+        (
+            "inverse_flattening",
+            Values::Double(vec![ellipsoid.inverse_flattening()]),
+        ),
+        ("crs_wkt", text(&wkt)),
+        // Read by GDAL.
+        ("spatial_ref", text(&wkt)),
+        ("epsg_code", text(&crs.to_string())),
+    ]);
+    attributes
+}
+
+// Header of a 64-bit offset file without record dimension, with the (size, offset) of the data
+// of each variable in `sections`.
+fn header(
+    dimensions: &[(&str, usize)],
+    attributes: &[(&str, Values)],
+    variables: &[Variable],
+    sections: &[(usize, u64)],
+) -> Vec<u8> {
+    let mut bytes = b"CDF\x02".to_vec();
+    bytes.extend(0u32.to_be_bytes());
+
+    bytes.extend(NC_DIMENSION.to_be_bytes());
+    bytes.extend((dimensions.len() as u32).to_be_bytes());
+    for (name, len) in dimensions {
+        push_name(&mut bytes, name);
+        bytes.extend((*len as u32).to_be_bytes());
+    }
+
+    push_attributes(&mut bytes, attributes);
+
+    bytes.extend(NC_VARIABLE.to_be_bytes());
+    bytes.extend((variables.len() as u32).to_be_bytes());
+    for (variable, (size, offset)) in variables.iter().zip(sections) {
+        push_name(&mut bytes, variable.name);
+        bytes.extend((variable.dimensions.len() as u32).to_be_bytes());
+        for id in &variable.dimensions {
+            bytes.extend(id.to_be_bytes());
+        }
+        push_attributes(&mut bytes, &variable.attributes);
+        bytes.extend(variable.data.nc_type().to_be_bytes());
+        bytes.extend(u32::try_from(*size).unwrap_or(u32::MAX).to_be_bytes());
+        bytes.extend(offset.to_be_bytes());
+    }
+    bytes
+}
+
+fn push_name(bytes: &mut Vec<u8>, name: &str) {
+    bytes.extend((name.len() as u32).to_be_bytes());
+    bytes.extend(text(name).bytes());
+}
+
+fn push_attributes(bytes: &mut Vec<u8>, attributes: &[(&str, Values)]) {
+    if attributes.is_empty() {
+        // ABSENT: a zero tag and a zero count.
+        bytes.extend([0; 8]);
+        return;
+    }
+    bytes.extend(NC_ATTRIBUTE.to_be_bytes());
+    bytes.extend((attributes.len() as u32).to_be_bytes());
+    for (name, values) in attributes {
+        push_name(bytes, name);
+        bytes.extend(values.nc_type().to_be_bytes());
+        bytes.extend((values.len() as u32).to_be_bytes());
+        bytes.extend(values.bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::Grid;
+
+    // Map of 3 x 2 points 100 m apart, with a missing height in the south-west corner.
+    fn map(crs: Crs) -> ElevationGrid {
+        let (x, y) = match crs {
+            Crs::Wgs84 => (vec![3., 3.001, 3.002], vec![46.5, 46.501]),
+            Crs::Projected { .. } => (
+                vec![700_000., 700_100., 700_200.],
+                vec![6_600_000., 6_600_100.],
+            ),
+        };
+        let grid = Grid {
+            crs,
+            center: (3., 46.5),
+            x,
+            y,
+            x_resolution: 100.,
+            y_resolution: 100.,
+        };
+        ElevationGrid::new(grid, vec![f64::NAN, 2., 3., 4., 5., 6.]).with_source("test")
+    }
+
+    // Attribute read back from a file, its values left undecoded.
+    struct Attribute {
+        name: String,
+        value: Vec<u8>,
+    }
+
+    struct VariableHeader {
+        name: String,
+        dimensions: Vec<u32>,
+        attributes: Vec<Attribute>,
+        nc_type: u32,
+        size: u32,
+        offset: u64,
+    }
+
+    struct Header {
+        dimensions: Vec<(String, u32)>,
+        attributes: Vec<Attribute>,
+        variables: Vec<VariableHeader>,
+        len: usize,
+    }
+
+    struct Reader<'a> {
+        bytes: &'a [u8],
+        position: usize,
+    }
+
+    impl Reader<'_> {
+        fn take(&mut self, len: usize) -> &[u8] {
+            let slice = &self.bytes[self.position..self.position + len];
+            self.position += len;
+            slice
+        }
+
+        fn u32(&mut self) -> u32 {
+            u32::from_be_bytes(self.take(4).try_into().unwrap())
+        }
+
+        fn u64(&mut self) -> u64 {
+            u64::from_be_bytes(self.take(8).try_into().unwrap())
+        }
+
+        // `len` bytes followed by zeros up to a multiple of 4.
+        fn padded(&mut self, len: usize) -> Vec<u8> {
+            let bytes = self.take(len).to_vec();
+            let padding = len.next_multiple_of(4) - len;
+            assert!(self.take(padding).iter().all(|&b| b == 0));
+            bytes
+        }
+
+        fn name(&mut self) -> String {
+            let len = self.u32() as usize;
+            String::from_utf8(self.padded(len)).unwrap()
+        }
+
+        fn attributes(&mut self) -> Vec<Attribute> {
+            let (tag, count) = (self.u32(), self.u32());
+            assert!(tag == NC_ATTRIBUTE || (tag, count) == (0, 0));
+            (0..count)
+                .map(|_| {
+                    let name = self.name();
+                    let size = match self.u32() {
+                        NC_CHAR => 1,
+                        NC_INT | NC_FLOAT => 4,
+                        NC_DOUBLE => 8,
+                        nc_type => panic!("unexpected type {}", nc_type),
+                    };
+                    let len = self.u32() as usize;
+                    let value = self.padded(len * size);
+                    Attribute { name, value }
+                })
+                .collect()
+        }
+    }
+
+    fn read_header(bytes: &[u8]) -> Header {
+        let mut reader = Reader { bytes, position: 0 };
+        assert_eq!(reader.take(4), b"CDF\x02");
+        assert_eq!(reader.u32(), 0, "no records");
+        assert_eq!(reader.u32(), NC_DIMENSION);
+        let dimensions = (0..reader.u32())
+            .map(|_| (reader.name(), reader.u32()))
+            .collect();
+        let attributes = reader.attributes();
+        assert_eq!(reader.u32(), NC_VARIABLE);
+        let variables = (0..reader.u32())
+            .map(|_| VariableHeader {
+                name: reader.name(),
+                dimensions: (0..reader.u32()).map(|_| reader.u32()).collect(),
+                attributes: reader.attributes(),
+                nc_type: reader.u32(),
+                size: reader.u32(),
+                offset: reader.u64(),
+            })
+            .collect();
+        Header {
+            dimensions,
+            attributes,
+            variables,
+            len: reader.position,
+        }
+    }
+
+    fn save(data: &ElevationGrid, name: &str) -> Vec<u8> {
+        let path = std::env::temp_dir().join(format!("{}-{}.nc", name, std::process::id()));
+        let path = path.to_str().unwrap();
+        save_netcdf(path, data).unwrap();
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        bytes
+    }
+
+    fn attribute<'a>(attributes: &'a [Attribute], name: &str) -> &'a [u8] {
+        &attributes.iter().find(|a| a.name == name).unwrap().value
+    }
+
+    // Data of a variable, split into values of `size` bytes.
+    fn values<'a>(bytes: &'a [u8], variable: &VariableHeader, size: usize) -> Vec<&'a [u8]> {
+        let begin = variable.offset as usize;
+        bytes[begin..begin + variable.size as usize]
+            .chunks(size)
+            .collect()
+    }
+
+    #[test]
+    fn layout() {
+        let bytes = save(&map(Crs::lambert93()), "layout");
+        let header = read_header(&bytes);
+        assert_eq!(
+            header.dimensions,
+            [("y".to_string(), 2), ("x".to_string(), 3)]
+        );
+        assert_eq!(attribute(&header.attributes, "Conventions"), b"CF-1.8");
+        assert_eq!(attribute(&header.attributes, "source"), b"test");
+        let names: Vec<&str> = header.variables.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, ["crs", "y", "x", "elevation"]);
+
+        // The data of the variables follow the header back to back, up to the end of the file.
+        let mut offset = header.len as u64;
+        for variable in &header.variables {
+            assert_eq!(variable.offset, offset);
+            offset += variable.size as u64;
+        }
+        assert_eq!(offset, bytes.len() as u64);
+
+        let elevation = &header.variables[3];
+        assert_eq!(elevation.dimensions, [0, 1]);
+        assert_eq!((elevation.nc_type, elevation.size), (NC_FLOAT, 24));
+        assert_eq!(attribute(&elevation.attributes, "grid_mapping"), b"crs");
+        assert_eq!(
+            attribute(&elevation.attributes, "_FillValue"),
+            (NODATA as f32).to_be_bytes()
+        );
+        let crs = &header.variables[0].attributes;
+        assert_eq!(
+            attribute(crs, "grid_mapping_name"),
+            b"lambert_conformal_conic"
+        );
+        assert_eq!(attribute(crs, "crs_wkt"), Crs::lambert93().wkt().as_bytes());
+    }
+
+    #[test]
+    fn heights_north_up() {
+        let bytes = save(&map(Crs::lambert93()), "heights");
+        let header = read_header(&bytes);
+        let heights: Vec<f32> = values(&bytes, &header.variables[3], 4)
+            .into_iter()
+            .map(|b| f32::from_be_bytes(b.try_into().unwrap()))
+            .collect();
+        // The northern row comes first, the heights of the map being x-major from the south.
+        assert_eq!(heights, [2., 4., 6., NODATA as f32, 3., 5.]);
+        let y: Vec<f64> = values(&bytes, &header.variables[1], 8)
+            .into_iter()
+            .map(|b| f64::from_be_bytes(b.try_into().unwrap()))
+            .collect();
+        assert_eq!(y, [6_600_100., 6_600_000.]);
+    }
+
+    #[test]
+    fn geographic_axes() {
+        let header = read_header(&save(&map(Crs::Wgs84), "geographic"));
+        assert_eq!(
+            header.dimensions,
+            [("lat".to_string(), 2), ("lon".to_string(), 3)]
+        );
+        let lon = &header.variables[2];
+        assert_eq!(lon.name, "lon");
+        assert_eq!(attribute(&lon.attributes, "units"), b"degrees_east");
+        assert_eq!(
+            attribute(&header.variables[0].attributes, "grid_mapping_name"),
+            b"latitude_longitude"
+        );
+    }
+}
@@ -0,0 +1,43 @@
+use anyhow::{Context, Result};
+use image::{GrayImage, RgbImage, RgbaImage};
+
+use crate::colormap::ColorRamp;
+use crate::grid::ElevationGrid;
+
+/// Save `data` as a north-up colour relief image, RGB unless some colours are transparent.
+pub fn save_color_relief(path_image: &str, data: &ElevationGrid, ramp: &ColorRamp) -> Result<()> {
+    let (nx, ny) = data.grid.shape();
+    let colors = ramp.colorize(&data.rows_north_up());
+    if colors.iter().all(|color| color[3] == u8::MAX) {
+        let pixels = colors.iter().flat_map(|c| [c[0], c[1], c[2]]).collect();
+        RgbImage::from_vec(nx as u32, ny as u32, pixels)
+            .context("Heights do not match the map size")?
+            .save(path_image)
+    } else {
+        RgbaImage::from_vec(nx as u32, ny as u32, colors.concat())
+            .context("Heights do not match the map size")?
+            .save(path_image)
+    }
+    .context("Failed to save the image")
+}
+
+/// Save a [`hillshade`](crate::terrain::hillshade) as a north-up 8-bit grayscale image, from 1
+/// to 255, with 0 for the missing heights.
+pub fn save_hillshade_image(path_image: &str, shade: &ElevationGrid) -> Result<()> {
+    let (nx, ny) = shade.grid.shape();
+    let pixels = shade
+        .rows_north_up()
+        .into_iter()
+        .map(|value| {
+            if value.is_nan() {
+                0
+            } else {
+                value.round().clamp(1., 255.) as u8
+            }
+        })
+        .collect();
+    GrayImage::from_vec(nx as u32, ny as u32, pixels)
+        .context("Heights do not match the map size")?
+        .save(path_image)
+        .context("Failed to save the image")
+}
@@ -0,0 +1,203 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use super::utc_now;
+use crate::contour::Contour;
+use crate::hydrology::Polygon;
+use crate::projection::Crs;
+
+const FILE_CODE: i32 = 9994;
+const VERSION: i32 = 1000;
+const SHAPE_POLYLINE: i32 = 3;
+const SHAPE_POLYGON: i32 = 5;
+// Size of the headers of the .shp and .shx files and of a record.
+const HEADER_SIZE: usize = 100;
+const RECORD_HEADER_SIZE: usize = 8;
+
+// Field of the attribute table: name, dBASE type, width and decimals.
+type Field = (&'static str, u8, u8, u8);
+
+/// Save `contours` as an ESRI Shapefile of polylines in the coordinates of `crs`.
+///
+/// `path` is the `.shp` file; the index (`.shx`), attribute table (`.dbf`, with the
+/// `ELEVATION` and `INDEX` fields) and projection (`.prj`) files are written next to it.
+pub fn save_contours_shapefile(path: &str, contours: &[Contour], crs: Crs) -> Result<()> {
+    let shapes: Vec<Vec<&[(f64, f64)]>> = contours
+        .iter()
+        .map(|contour| vec![&contour.points[..]])
+        .collect();
+    let values: Vec<Vec<String>> = contours
+        .iter()
+        .map(|contour| {
+            vec![
+                format!("{:>12.3}", contour.elevation),
+                if contour.index { "T" } else { "F" }.to_string(),
+            ]
+        })
+        .collect();
+    let fields = [("ELEVATION", b'N', 12, 3), ("INDEX", b'L', 1, 0)];
+    save_shapefile(path, SHAPE_POLYLINE, &shapes, &fields, &values, crs)
+}
+
+/// Save `polygons` as an ESRI Shapefile of polygons in the coordinates of `crs`, with their
+/// `AREA` in square meters, like [`save_contours_shapefile`].
+pub fn save_polygons_shapefile(path: &str, polygons: &[Polygon], crs: Crs) -> Result<()> {
+    // The outer rings of a shapefile go clockwise and the holes counterclockwise.
+    let reversed: Vec<Vec<Vec<(f64, f64)>>> = polygons
+        .iter()
+        .map(|polygon| {
+            std::iter::once(&polygon.exterior)
+                .chain(&polygon.holes)
+                .map(|ring| ring.iter().rev().copied().collect())
+                .collect()
+        })
+        .collect();
+    let shapes: Vec<Vec<&[(f64, f64)]>> = reversed
+        .iter()
+        .map(|rings| rings.iter().map(|ring| &ring[..]).collect())
+        .collect();
+    let values: Vec<Vec<String>> = polygons
+        .iter()
+        .map(|polygon| vec![format!("{:>18.1}", polygon.area)])
+        .collect();
+    let fields = [("AREA", b'N', 18, 1)];
+    save_shapefile(path, SHAPE_POLYGON, &shapes, &fields, &values, crs)
+}
+
+// Write the shapes made of the given parts, with their attributes already formatted to the
+// width of the fields.
+fn save_shapefile(
+    path: &str,
+    shape_type: i32,
+    shapes: &[Vec<&[(f64, f64)]>],
+    fields: &[Field],
+    values: &[Vec<String>],
+    crs: Crs,
+) -> Result<()> {
+    let path = Path::new(path);
+    let records: Vec<Vec<u8>> = shapes
+        .iter()
+        .map(|parts| record(shape_type, parts))
+        .collect();
+    let bbox = shapes
+        .iter()
+        .flatten()
+        .flat_map(|part| part.iter())
+        .fold(None, |bbox, &point| Some(extend(bbox, point)))
+        .unwrap_or_default();
+
+    let shp_size = HEADER_SIZE
+        + records
+            .iter()
+            .map(|r| RECORD_HEADER_SIZE + r.len())
+            .sum::<usize>();
+    let shx_size = HEADER_SIZE + RECORD_HEADER_SIZE * records.len();
+    let mut shp = header(shape_type, shp_size, bbox);
+    let mut shx = header(shape_type, shx_size, bbox);
+    for (number, record) in records.iter().enumerate() {
+        // Offsets and lengths are counted in 16-bit words.
+        shx.extend(((shp.len() / 2) as i32).to_be_bytes());
+        shx.extend(((record.len() / 2) as i32).to_be_bytes());
+        shp.extend((number as i32 + 1).to_be_bytes());
+        shp.extend(((record.len() / 2) as i32).to_be_bytes());
+        shp.extend(record);
+    }
+
+    let files = [
+        (path.to_path_buf(), shp),
+        (path.with_extension("shx"), shx),
+        (path.with_extension("dbf"), attributes(fields, values)),
+        (path.with_extension("prj"), crs.wkt().into_bytes()),
+    ];
+    for (path, bytes) in files {
+        std::fs::write(&path, bytes)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+// Extreme coordinates (min x, min y, max x, max y) of points.
+type Bbox = (f64, f64, f64, f64);
+
+fn extend(bbox: Option<Bbox>, (x, y): (f64, f64)) -> Bbox {
+    match bbox {
+        Some((min_x, min_y, max_x, max_y)) => {
+            (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+        }
+        None => (x, y, x, y),
+    }
+}
+
+// Header of the .shp and .shx files, of `size` bytes with this header.
+fn header(shape_type: i32, size: usize, (min_x, min_y, max_x, max_y): Bbox) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(size);
+    bytes.extend(FILE_CODE.to_be_bytes());
+    bytes.extend([0; 20]);
+    bytes.extend(((size / 2) as i32).to_be_bytes());
+    bytes.extend(VERSION.to_le_bytes());
+    bytes.extend(shape_type.to_le_bytes());
+    // Bounding box in x and y, then the unused ranges of z and m.
+    for value in [min_x, min_y, max_x, max_y, 0., 0., 0., 0.] {
+        bytes.extend(value.to_le_bytes());
+    }
+    bytes
+}
+
+// Content of the record of a polyline or polygon made of several parts.
+fn record(shape_type: i32, parts: &[&[(f64, f64)]]) -> Vec<u8> {
+    let points = parts.iter().flat_map(|part| part.iter());
+    let (min_x, min_y, max_x, max_y) = points
+        .clone()
+        .fold(None, |bbox, &point| Some(extend(bbox, point)))
+        .unwrap_or_default();
+    let mut bytes = Vec::new();
+    bytes.extend(shape_type.to_le_bytes());
+    for value in [min_x, min_y, max_x, max_y] {
+        bytes.extend(value.to_le_bytes());
+    }
+    bytes.extend((parts.len() as i32).to_le_bytes());
+    bytes.extend((points.clone().count() as i32).to_le_bytes());
+    let mut start = 0;
+    for part in parts {
+        bytes.extend((start as i32).to_le_bytes());
+        start += part.len();
+    }
+    for (x, y) in points {
+        bytes.extend(x.to_le_bytes());
+        bytes.extend(y.to_le_bytes());
+    }
+    bytes
+}
+
+// dBASE III table of the attributes.
+fn attributes(fields: &[Field], values: &[Vec<String>]) -> Vec<u8> {
+    let header_size = 32 + 32 * fields.len() + 1;
+    let record_size = 1 + fields.iter().map(|f| f.2 as usize).sum::<usize>();
+    let ((year, month, day), _) = utc_now();
+
+    let mut bytes = vec![0x03, (year - 1900) as u8, month as u8, day as u8];
+    bytes.extend((values.len() as u32).to_le_bytes());
+    bytes.extend((header_size as u16).to_le_bytes());
+    bytes.extend((record_size as u16).to_le_bytes());
+    bytes.extend([0; 20]);
+    for (name, kind, width, decimals) in fields {
+        let mut field = [0; 32];
+        field[..name.len()].copy_from_slice(name.as_bytes());
+        field[11] = *kind;
+        field[16] = *width;
+        field[17] = *decimals;
+        bytes.extend(field);
+    }
+    bytes.push(0x0D);
+
+    // Records start with the deletion flag.
+    for record in values {
+        bytes.push(b' ');
+        for value in record {
+            bytes.extend(value.as_bytes());
+        }
+    }
+    bytes.push(0x1A);
+    bytes
+}
@@ -0,0 +1,109 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use anyhow::{Context, Result};
+
+use crate::contour::Contour;
+use crate::grid::Grid;
+use crate::hydrology::Polygon;
+
+const CONTOUR_STYLE: &str = "\
+path { fill: none; stroke: #8c5a2b; stroke-width: 0.8; vector-effect: non-scaling-stroke; }
+path.index { stroke-width: 1.6; }";
+const POLYGON_STYLE: &str = "\
+path { fill: #3d85c6; fill-opacity: 0.3; fill-rule: evenodd; stroke: #1c4587; stroke-width: 1.2;
+  vector-effect: non-scaling-stroke; }";
+
+// SVG drawing of a map north-up, with one unit per point of its grid.
+struct Drawing {
+    writer: BufWriter<File>,
+    west: f64,
+    north: f64,
+    dx: f64,
+    dy: f64,
+}
+
+impl Drawing {
+    fn create(path: &str, grid: &Grid, style: &str) -> Result<Self> {
+        let (nx, ny) = grid.shape();
+        let (dx, dy) = grid.spacing();
+        let file = File::create(path).with_context(|| format!("Failed to create {}", path))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(
+            writer,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{nx}" height="{ny}" viewBox="-0.5 -0.5 {nx} {ny}">"#
+        )?;
+        writeln!(writer, "<style>\n{}\n</style>", style)?;
+        Ok(Self {
+            writer,
+            west: grid.x.first().copied().unwrap_or_default(),
+            north: grid.y.last().copied().unwrap_or_default(),
+            dx,
+            dy,
+        })
+    }
+
+    // Path data of a line in the coordinates of the CRS, closed if `closed`.
+    fn path_data(&self, points: &[(f64, f64)], closed: bool) -> String {
+        let mut d = String::new();
+        for (i, (x, y)) in points.iter().enumerate() {
+            let command = if i == 0 { 'M' } else { 'L' };
+            d += &format!(
+                "{}{:.2} {:.2}",
+                command,
+                (x - self.west) / self.dx,
+                (self.north - y) / self.dy
+            );
+        }
+        if closed {
+            d.push('Z');
+        }
+        d
+    }
+
+    fn finish(mut self) -> Result<()> {
+        writeln!(self.writer, "</svg>")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Save `contours` of a map on `grid` as a stand-alone SVG drawing, north-up with one unit per
+/// point of the grid like the heightmap images, so that it can be laid over them. The index
+/// contours are drawn thicker and each line has its elevation as title.
+pub fn save_contours_svg(path: &str, contours: &[Contour], grid: &Grid) -> Result<()> {
+    let mut drawing = Drawing::create(path, grid, CONTOUR_STYLE)?;
+    for contour in contours {
+        let d = drawing.path_data(&contour.points, contour.is_closed());
+        writeln!(
+            drawing.writer,
+            r#"<path class="{}" d="{}"><title>{} m</title></path>"#,
+            if contour.index {
+                "contour index"
+            } else {
+                "contour"
+            },
+            d,
+            contour.elevation
+        )?;
+    }
+    drawing.finish()
+}
+
+/// Save `polygons` of a map on `grid` as a stand-alone SVG drawing laid out like
+/// [`save_contours_svg`], each polygon having its area as title.
+pub fn save_polygons_svg(path: &str, polygons: &[Polygon], grid: &Grid) -> Result<()> {
+    let mut drawing = Drawing::create(path, grid, POLYGON_STYLE)?;
+    for polygon in polygons {
+        let d: String = std::iter::once(&polygon.exterior)
+            .chain(&polygon.holes)
+            .map(|ring| drawing.path_data(ring, true))
+            .collect();
+        writeln!(
+            drawing.writer,
+            r#"<path d="{}"><title>{:.0} m²</title></path>"#,
+            d, polygon.area
+        )?;
+    }
+    drawing.finish()
+}
@@ -0,0 +1,75 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use super::NODATA;
+use crate::grid::ElevationGrid;
+
+/// Save `data` as an ESRI ASCII Grid, the format of the RGE ALTI downloads, with the WKT of its
+/// CRS in a projection (`.prj`) file next to it.
+///
+/// The header gives the lower-left corner of the lower-left cell, each cell being centered on a
+/// point of the grid. Non-square cells, as on longitude/latitude grids, are written with the
+/// `dx`/`dy` extension of GDAL instead of `cellsize`, which other readers may reject. The rows go
+/// from north to south and the missing heights are [`NODATA`].
+pub fn save_ascii_grid(output: &str, data: &ElevationGrid) -> Result<()> {
+    let (nx, ny) = data.grid.shape();
+    let (dx, dy) = data.grid.spacing();
+    let file = File::create(output).with_context(|| format!("Failed to create {}", output))?;
+    let mut writer = BufWriter::new(file);
+
+    writeln!(writer, "ncols {}", nx)?;
+    writeln!(writer, "nrows {}", ny)?;
+    writeln!(
+        writer,
+        "xllcorner {}",
+        data.grid.x.first().copied().unwrap_or_default() - 0.5 * dx
+    )?;
+    writeln!(
+        writer,
+        "yllcorner {}",
+        data.grid.y.first().copied().unwrap_or_default() - 0.5 * dy
+    )?;
+    if (dx - dy).abs() <= 1e-9 * dx.abs().max(dy.abs()) {
+        writeln!(writer, "cellsize {}", dx)?;
+    } else {
+        writeln!(writer, "dx {}", dx)?;
+        writeln!(writer, "dy {}", dy)?;
+    }
+    writeln!(writer, "NODATA_value {}", NODATA)?;
+
+    let rows = data.rows_north_up();
+    for row in rows.chunks(nx.max(1)) {
+        let line: Vec<String> = row
+            .iter()
+            .map(|h| if h.is_nan() { NODATA } else { *h }.to_string())
+            .collect();
+        writeln!(writer, "{}", line.join(" "))?;
+    }
+    writer.flush()?;
+
+    let prj = Path::new(output).with_extension("prj");
+    std::fs::write(&prj, data.grid.crs.wkt())
+        .with_context(|| format!("Failed to write {}", prj.display()))?;
+    Ok(())
+}
+
+/// Save `data` as `x y z` lines in the coordinates of its CRS, from north to south and west to
+/// east. The points without data are left out.
+pub fn save_xyz(output: &str, data: &ElevationGrid) -> Result<()> {
+    let (nx, ny) = data.grid.shape();
+    let file = File::create(output).with_context(|| format!("Failed to create {}", output))?;
+    let mut writer = BufWriter::new(file);
+    for iy in (0..ny).rev() {
+        for ix in 0..nx {
+            let height = data.get(ix, iy);
+            if !height.is_nan() {
+                writeln!(writer, "{} {} {}", data.grid.x[ix], data.grid.y[iy], height)?;
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
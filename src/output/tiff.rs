@@ -0,0 +1,346 @@
+//! Minimal little-endian TIFF writer: images made of any tags and data blocks.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use flate2::write::ZlibEncoder;
+use weezl::BitOrder;
+
+pub const IMAGE_WIDTH: u16 = 256;
+pub const IMAGE_LENGTH: u16 = 257;
+pub const NEW_SUBFILE_TYPE: u16 = 254;
+pub const BITS_PER_SAMPLE: u16 = 258;
+pub const COMPRESSION: u16 = 259;
+pub const PHOTOMETRIC_INTERPRETATION: u16 = 262;
+pub const STRIP_OFFSETS: u16 = 273;
+pub const SAMPLES_PER_PIXEL: u16 = 277;
+pub const ROWS_PER_STRIP: u16 = 278;
+pub const STRIP_BYTE_COUNTS: u16 = 279;
+pub const PLANAR_CONFIGURATION: u16 = 284;
+pub const PREDICTOR: u16 = 317;
+pub const TILE_WIDTH: u16 = 322;
+pub const TILE_LENGTH: u16 = 323;
+pub const TILE_OFFSETS: u16 = 324;
+pub const TILE_BYTE_COUNTS: u16 = 325;
+pub const SAMPLE_FORMAT: u16 = 339;
+
+pub const COMPRESSION_NONE: u16 = 1;
+pub const COMPRESSION_LZW: u16 = 5;
+pub const COMPRESSION_DEFLATE: u16 = 8;
+pub const SUBFILE_REDUCED_IMAGE: u32 = 1;
+pub const PREDICTOR_FLOATING_POINT: u16 = 3;
+pub const PHOTOMETRIC_BLACK_IS_ZERO: u16 = 1;
+pub const PLANAR_CHUNKY: u16 = 1;
+pub const SAMPLE_FORMAT_FLOAT: u16 = 3;
+
+/// Value of a TIFF tag.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Double(Vec<f64>),
+    Ascii(String),
+}
+
+impl Value {
+    fn type_code(&self) -> u16 {
+        match self {
+            Value::Ascii(_) => 2,
+            Value::Short(_) => 3,
+            Value::Long(_) => 4,
+            Value::Double(_) => 12,
+        }
+    }
+
+    fn count(&self) -> u32 {
+        match self {
+            Value::Short(v) => v.len() as u32,
+            Value::Long(v) => v.len() as u32,
+            Value::Double(v) => v.len() as u32,
+            Value::Ascii(s) => s.len() as u32 + 1,
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            Value::Short(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            Value::Long(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            Value::Double(v) => v.iter().flat_map(|x| x.to_le_bytes()).collect(),
+            Value::Ascii(s) => s.bytes().chain([0]).collect(),
+        }
+    }
+}
+
+/// An image of a TIFF file: its tags and its data blocks, strips or tiles.
+#[derive(Debug, Clone)]
+pub struct TiffImage {
+    pub tags: BTreeMap<u16, Value>,
+    pub blocks: Vec<Vec<u8>>,
+    /// Tag receiving the offsets of the blocks, e.g. [`STRIP_OFFSETS`].
+    pub offsets_tag: u16,
+    /// Tag receiving the sizes of the blocks, e.g. [`STRIP_BYTE_COUNTS`].
+    pub byte_counts_tag: u16,
+}
+
+impl TiffImage {
+    /// Image stored as strips of `rows_per_strip` rows.
+    pub fn strips(
+        width: usize,
+        height: usize,
+        rows_per_strip: usize,
+        blocks: Vec<Vec<u8>>,
+    ) -> Self {
+        let mut tags = BTreeMap::new();
+        tags.insert(IMAGE_WIDTH, Value::Long(vec![width as u32]));
+        tags.insert(IMAGE_LENGTH, Value::Long(vec![height as u32]));
+        tags.insert(ROWS_PER_STRIP, Value::Long(vec![rows_per_strip as u32]));
+        Self {
+            tags,
+            blocks,
+            offsets_tag: STRIP_OFFSETS,
+            byte_counts_tag: STRIP_BYTE_COUNTS,
+        }
+    }
+
+    pub fn set(&mut self, tag: u16, value: Value) {
+        self.tags.insert(tag, value);
+    }
+
+    // Tags with the byte counts and the given offsets of the blocks.
+    fn tags_with_offsets(&self, offsets: Vec<u32>) -> BTreeMap<u16, Value> {
+        let mut tags = self.tags.clone();
+        let counts = self.blocks.iter().map(|b| b.len() as u32).collect();
+        tags.insert(self.byte_counts_tag, Value::Long(counts));
+        tags.insert(self.offsets_tag, Value::Long(offsets));
+        tags
+    }
+}
+
+// Size of an IFD with its out-of-line values, each padded to an even offset.
+fn ifd_size(tags: &BTreeMap<u16, Value>) -> usize {
+    let values: usize = tags
+        .values()
+        .map(|v| v.bytes().len())
+        .filter(|len| *len > 4)
+        .map(|len| len + len % 2)
+        .sum();
+    2 + 12 * tags.len() + 4 + values
+}
+
+// Serialize an IFD at `offset`, followed by its out-of-line values.
+fn write_ifd(out: &mut Vec<u8>, tags: &BTreeMap<u16, Value>, offset: usize, next_ifd: u32) {
+    let mut overflow = Vec::new();
+    let overflow_offset = offset + 2 + 12 * tags.len() + 4;
+    out.extend((tags.len() as u16).to_le_bytes());
+    for (tag, value) in tags {
+        let bytes = value.bytes();
+        out.extend(tag.to_le_bytes());
+        out.extend(value.type_code().to_le_bytes());
+        out.extend(value.count().to_le_bytes());
+        if bytes.len() <= 4 {
+            let mut inline = bytes;
+            inline.resize(4, 0);
+            out.extend(inline);
+        } else {
+            out.extend(((overflow_offset + overflow.len()) as u32).to_le_bytes());
+            overflow.extend(&bytes);
+            if overflow.len() % 2 == 1 {
+                overflow.push(0);
+            }
+        }
+    }
+    out.extend(next_ifd.to_le_bytes());
+    out.extend(overflow);
+}
+
+/// Write a TIFF file with the given images.
+///
+/// All the IFDs come first, followed by the data of the images in reverse order, so that the
+/// overviews of a cloud-optimized GeoTIFF are stored before the full resolution image.
+pub fn write_tiff(path: &str, images: &[TiffImage]) -> Result<()> {
+    // The IFD sizes only depend on the number of blocks, not on their offsets.
+    let placeholders: Vec<_> = images
+        .iter()
+        .map(|image| image.tags_with_offsets(vec![0; image.blocks.len()]))
+        .collect();
+    let mut ifd_offsets = Vec::with_capacity(images.len());
+    let mut offset = 8;
+    for tags in &placeholders {
+        ifd_offsets.push(offset);
+        offset += ifd_size(tags);
+    }
+
+    let mut block_offsets = vec![Vec::new(); images.len()];
+    for (index, image) in images.iter().enumerate().rev() {
+        for block in &image.blocks {
+            block_offsets[index].push(offset as u32);
+            offset += block.len();
+        }
+    }
+    anyhow::ensure!(
+        offset <= u32::MAX as usize,
+        "The image is too large for a TIFF file"
+    );
+
+    let mut header = Vec::with_capacity(ifd_offsets.last().copied().unwrap_or(8));
+    header.extend(b"II");
+    header.extend(42u16.to_le_bytes());
+    header.extend((ifd_offsets[0] as u32).to_le_bytes());
+    for (index, image) in images.iter().enumerate() {
+        let next_ifd = ifd_offsets.get(index + 1).copied().unwrap_or(0) as u32;
+        let tags = image.tags_with_offsets(std::mem::take(&mut block_offsets[index]));
+        write_ifd(&mut header, &tags, ifd_offsets[index], next_ifd);
+    }
+
+    let file = File::create(path).with_context(|| format!("Failed to create {}", path))?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(&header)?;
+    for image in images.iter().rev() {
+        for block in &image.blocks {
+            writer.write_all(block)?;
+        }
+    }
+    writer.flush().context("Failed to write the TIFF file")?;
+    Ok(())
+}
+
+/// Compression of the data blocks of a TIFF image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Deflate,
+    Lzw,
+}
+
+impl Compression {
+    fn tag_value(&self) -> u16 {
+        match self {
+            Compression::None => COMPRESSION_NONE,
+            Compression::Deflate => COMPRESSION_DEFLATE,
+            Compression::Lzw => COMPRESSION_LZW,
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "deflate" => Ok(Compression::Deflate),
+            "lzw" => Ok(Compression::Lzw),
+            _ => anyhow::bail!("Unknown compression '{}', expected none, deflate or lzw", s),
+        }
+    }
+}
+
+// Encode a block of rows of `width` Float32 values. Compressed blocks use the floating point
+// predictor: the bytes of each row are split in planes, most significant first, and
+// differenced.
+fn encode_block(values: &[f32], width: usize, compression: Compression) -> Result<Vec<u8>> {
+    if compression == Compression::None {
+        return Ok(values.iter().flat_map(|v| v.to_le_bytes()).collect());
+    }
+    let mut bytes = Vec::with_capacity(4 * values.len());
+    for row in values.chunks(width) {
+        let start = bytes.len();
+        bytes.resize(start + 4 * row.len(), 0);
+        let planes = &mut bytes[start..];
+        for (i, value) in row.iter().enumerate() {
+            for (plane, byte) in value.to_be_bytes().into_iter().enumerate() {
+                planes[plane * row.len() + i] = byte;
+            }
+        }
+        for i in (1..planes.len()).rev() {
+            planes[i] = planes[i].wrapping_sub(planes[i - 1]);
+        }
+    }
+    match compression {
+        Compression::None => unreachable!(),
+        Compression::Deflate => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&bytes)?;
+            Ok(encoder.finish()?)
+        }
+        Compression::Lzw => {
+            Ok(weezl::encode::Encoder::with_tiff_size_switch(BitOrder::Msb, 8).encode(&bytes)?)
+        }
+    }
+}
+
+// Tags shared by the single-band Float32 images.
+fn set_float32_tags(image: &mut TiffImage, compression: Compression) {
+    image.set(BITS_PER_SAMPLE, Value::Short(vec![32]));
+    image.set(COMPRESSION, Value::Short(vec![compression.tag_value()]));
+    image.set(
+        PHOTOMETRIC_INTERPRETATION,
+        Value::Short(vec![PHOTOMETRIC_BLACK_IS_ZERO]),
+    );
+    image.set(SAMPLES_PER_PIXEL, Value::Short(vec![1]));
+    image.set(PLANAR_CONFIGURATION, Value::Short(vec![PLANAR_CHUNKY]));
+    image.set(SAMPLE_FORMAT, Value::Short(vec![SAMPLE_FORMAT_FLOAT]));
+    if compression != Compression::None {
+        image.set(PREDICTOR, Value::Short(vec![PREDICTOR_FLOATING_POINT]));
+    }
+}
+
+/// Single-band Float32 image of `width` x `height` row-major `values`, in strips.
+pub fn float32_image(
+    values: &[f32],
+    width: usize,
+    height: usize,
+    compression: Compression,
+) -> Result<TiffImage> {
+    // Strips of about 64 kB.
+    let rows_per_strip = (65536 / (4 * width.max(1))).clamp(1, height.max(1));
+    let blocks = values
+        .chunks(rows_per_strip * width.max(1))
+        .map(|strip| encode_block(strip, width, compression))
+        .collect::<Result<_>>()?;
+    let mut image = TiffImage::strips(width, height, rows_per_strip, blocks);
+    set_float32_tags(&mut image, compression);
+    Ok(image)
+}
+
+/// Single-band Float32 image of `width` x `height` row-major `values`, in square tiles of
+/// `tile` pixels. The tiles crossing the right and bottom edges are padded with `fill`.
+pub fn float32_tiled_image(
+    values: &[f32],
+    width: usize,
+    height: usize,
+    tile: usize,
+    fill: f32,
+    compression: Compression,
+) -> Result<TiffImage> {
+    let mut blocks = Vec::new();
+    for tile_row in 0..height.div_ceil(tile) {
+        for tile_col in 0..width.div_ceil(tile) {
+            let mut block = vec![fill; tile * tile];
+            for row in 0..tile.min(height - tile_row * tile) {
+                let start = (tile_row * tile + row) * width + tile_col * tile;
+                let len = tile.min(width - tile_col * tile);
+                block[row * tile..row * tile + len].copy_from_slice(&values[start..start + len]);
+            }
+            blocks.push(encode_block(&block, tile, compression)?);
+        }
+    }
+
+    let mut tags = BTreeMap::new();
+    tags.insert(IMAGE_WIDTH, Value::Long(vec![width as u32]));
+    tags.insert(IMAGE_LENGTH, Value::Long(vec![height as u32]));
+    tags.insert(TILE_WIDTH, Value::Long(vec![tile as u32]));
+    tags.insert(TILE_LENGTH, Value::Long(vec![tile as u32]));
+    let mut image = TiffImage {
+        tags,
+        blocks,
+        offsets_tag: TILE_OFFSETS,
+        byte_counts_tag: TILE_BYTE_COUNTS,
+    };
+    set_float32_tags(&mut image, compression);
+    Ok(image)
+}
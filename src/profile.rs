@@ -0,0 +1,259 @@
+//! Elevation profiles along a route.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use crate::grid::{LatLon, EARTH_RADIUS};
+
+/// Point of a profile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProfilePoint {
+    pub position: LatLon,
+    /// Distance from the start of the route along it, in meters.
+    pub distance: f64,
+    /// Height in meters, NaN if missing.
+    pub elevation: f64,
+}
+
+/// Totals of a profile, ignoring the missing heights.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ProfileSummary {
+    /// Length of the route in meters.
+    pub length: f64,
+    /// Sum of the climbs in meters.
+    pub gain: f64,
+    /// Sum of the descents in meters, positive.
+    pub loss: f64,
+    pub min_elevation: f64,
+    pub max_elevation: f64,
+    /// Steepest climb between two successive points, in percent.
+    pub max_grade: f64,
+    /// Steepest descent between two successive points, in percent, negative.
+    pub min_grade: f64,
+}
+
+/// Read the points of a route from a GPX file (its track points, or else its route points)
+/// or from a GeoJSON LineString, MultiLineString, or the first feature with such a geometry.
+pub fn read_route(path: &str) -> Result<Vec<LatLon>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let route = match extension.as_str() {
+        "gpx" => parse_gpx(&text),
+        "geojson" | "json" => parse_geojson(&text),
+        _ => anyhow::bail!(
+            "Unknown route format of {}, expected .gpx or .geojson",
+            path
+        ),
+    }
+    .with_context(|| format!("Invalid route {}", path))?;
+    anyhow::ensure!(
+        route.len() >= 2,
+        "The route {} has less than 2 points",
+        path
+    );
+    Ok(route)
+}
+
+fn parse_gpx(text: &str) -> Result<Vec<LatLon>> {
+    let document = roxmltree::Document::parse(text)?;
+    let points = |name: &str| -> Result<Vec<LatLon>> {
+        document
+            .descendants()
+            .filter(|node| node.has_tag_name(name))
+            .map(|node| {
+                let coordinate = |attribute: &str| -> Result<f64> {
+                    node.attribute(attribute)
+                        .with_context(|| format!("No {} attribute in a {}", attribute, name))?
+                        .parse()
+                        .with_context(|| format!("Invalid {} in a {}", attribute, name))
+                };
+                Ok(LatLon {
+                    latitude: coordinate("lat")?,
+                    longitude: coordinate("lon")?,
+                })
+            })
+            .collect()
+    };
+    let track = points("trkpt")?;
+    if track.is_empty() {
+        points("rtept")
+    } else {
+        Ok(track)
+    }
+}
+
+fn parse_geojson(text: &str) -> Result<Vec<LatLon>> {
+    let value: Value = serde_json::from_str(text)?;
+    line_string(&value).context("No LineString in the GeoJSON")
+}
+
+// Points of the first line string in a GeoJSON object.
+fn line_string(value: &Value) -> Option<Vec<LatLon>> {
+    let position = |position: &Value| {
+        Some(LatLon {
+            longitude: position.get(0)?.as_f64()?,
+            latitude: position.get(1)?.as_f64()?,
+        })
+    };
+    let coordinates = value.get("coordinates");
+    match value.get("type")?.as_str()? {
+        "LineString" => coordinates?.as_array()?.iter().map(position).collect(),
+        "MultiLineString" => coordinates?
+            .as_array()?
+            .iter()
+            .flat_map(|line| line.as_array().into_iter().flatten())
+            .map(position)
+            .collect(),
+        "Feature" => line_string(value.get("geometry")?),
+        "FeatureCollection" => value
+            .get("features")?
+            .as_array()?
+            .iter()
+            .find_map(line_string),
+        _ => None,
+    }
+}
+
+/// Great-circle distance in meters between two points.
+pub fn distance(a: &LatLon, b: &LatLon) -> f64 {
+    let (lat_a, lat_b) = (a.latitude.to_radians(), b.latitude.to_radians());
+    let half_lat = 0.5 * (lat_b - lat_a);
+    let half_lon = 0.5 * (b.longitude - a.longitude).to_radians();
+    let h = half_lat.sin().powi(2) + lat_a.cos() * lat_b.cos() * half_lon.sin().powi(2);
+    2. * EARTH_RADIUS * h.sqrt().asin()
+}
+
+/// Points along `route` at most `spacing` meters apart, evenly spread on each of its segments,
+/// with their distance from the start and NaN heights.
+pub fn densify(route: &[LatLon], spacing: f64) -> Vec<ProfilePoint> {
+    let mut points = Vec::new();
+    let mut start = 0.;
+    for (i, pair) in route.windows(2).enumerate() {
+        let (a, b) = (&pair[0], &pair[1]);
+        let length = distance(a, b);
+        let steps = if spacing > 0. {
+            (length / spacing).ceil().max(1.) as usize
+        } else {
+            1
+        };
+        // The end of a segment is the start of the next one, only added after the last one.
+        let last = if i == route.len() - 2 {
+            steps
+        } else {
+            steps - 1
+        };
+        for step in 0..=last {
+            let t = step as f64 / steps as f64;
+            points.push(ProfilePoint {
+                position: LatLon {
+                    latitude: a.latitude + t * (b.latitude - a.latitude),
+                    longitude: a.longitude + t * (b.longitude - a.longitude),
+                },
+                distance: start + t * length,
+                elevation: f64::NAN,
+            });
+        }
+        start += length;
+    }
+    points
+}
+
+// Grade and cumulative climbs at a point of a profile.
+struct Climb {
+    // Grade in percent from the previous point with a height, NaN if none.
+    grade: f64,
+    // Gain and loss from the start, in meters.
+    gain: f64,
+    loss: f64,
+}
+
+// Climb at each point of `profile`, the missing heights being skipped.
+fn climbs(profile: &[ProfilePoint]) -> Vec<Climb> {
+    let (mut gain, mut loss) = (0., 0.);
+    let mut previous: Option<&ProfilePoint> = None;
+    let mut climbs = Vec::with_capacity(profile.len());
+    for point in profile {
+        let mut grade = f64::NAN;
+        if !point.elevation.is_nan() {
+            if let Some(previous) = previous {
+                let climb = point.elevation - previous.elevation;
+                if climb > 0. {
+                    gain += climb;
+                } else {
+                    loss -= climb;
+                }
+                let run = point.distance - previous.distance;
+                if run > 0. {
+                    grade = 100. * climb / run;
+                }
+            }
+            previous = Some(point);
+        }
+        climbs.push(Climb { grade, gain, loss });
+    }
+    climbs
+}
+
+/// Totals of `profile`: length, elevation gain and loss, extreme heights and grades.
+pub fn summarize(profile: &[ProfilePoint]) -> ProfileSummary {
+    let climbs = climbs(profile);
+    let heights = profile
+        .iter()
+        .map(|point| point.elevation)
+        .filter(|h| !h.is_nan());
+    let grades = climbs
+        .iter()
+        .map(|climb| climb.grade)
+        .filter(|g| !g.is_nan());
+    ProfileSummary {
+        length: profile.last().map_or(0., |point| point.distance),
+        gain: climbs.last().map_or(0., |climb| climb.gain),
+        loss: climbs.last().map_or(0., |climb| climb.loss),
+        min_elevation: heights.clone().fold(f64::NAN, f64::min),
+        max_elevation: heights.fold(f64::NAN, f64::max),
+        max_grade: grades.clone().fold(0., f64::max),
+        min_grade: grades.fold(0., f64::min),
+    }
+}
+
+/// Save `profile` as CSV, one line per point with its distance, position, elevation, grade in
+/// percent from the previous point, and the cumulative gain and loss from the start. The missing
+/// values are left empty.
+pub fn save_profile_csv(path: &str, profile: &[ProfilePoint]) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("Failed to create {}", path))?;
+    let mut writer = BufWriter::new(file);
+    let optional = |value: f64| {
+        if value.is_nan() {
+            String::new()
+        } else {
+            format!("{:.2}", value)
+        }
+    };
+    writeln!(
+        writer,
+        "distance,latitude,longitude,elevation,grade,gain,loss"
+    )?;
+    for (point, climb) in profile.iter().zip(climbs(profile)) {
+        writeln!(
+            writer,
+            "{:.2},{:.7},{:.7},{},{},{:.2},{:.2}",
+            point.distance,
+            point.position.latitude,
+            point.position.longitude,
+            optional(point.elevation),
+            optional(climb.grade),
+            climb.gain,
+            climb.loss
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,548 @@
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+use std::str::FromStr;
+
+/// Reference ellipsoid of a projection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ellipsoid {
+    /// Semi-major axis, in meters.
+    pub a: f64,
+    /// Flattening.
+    pub f: f64,
+}
+
+impl Ellipsoid {
+    /// Ellipsoid of RGF93 and ETRS89.
+    pub const GRS80: Ellipsoid = Ellipsoid {
+        a: 6378137.,
+        f: 1. / 298.257222101,
+    };
+    pub const WGS84: Ellipsoid = Ellipsoid {
+        a: 6378137.,
+        f: 1. / 298.257223563,
+    };
+
+    pub fn eccentricity(&self) -> f64 {
+        (self.f * (2. - self.f)).sqrt()
+    }
+
+    /// Inverse flattening, rounded to the digits of its definition.
+    pub fn inverse_flattening(&self) -> f64 {
+        (1e9 / self.f).round() / 1e9
+    }
+}
+
+/// Coordinate reference system of a grid.
+///
+/// Projected systems on RGF93/ETRS89 match WGS84 to well under a meter over France, so no
+/// datum shift is applied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Crs {
+    /// Longitude and latitude in degrees (EPSG:4326).
+    Wgs84,
+    /// Projected coordinates in meters.
+    Projected { epsg: u32, projection: Projection },
+}
+
+/// Map projection from longitude/latitude to plane coordinates in meters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    LambertConformalConic(LambertConformalConic),
+    TransverseMercator(TransverseMercator),
+}
+
+impl Crs {
+    /// RGF93 / Lambert-93 (EPSG:2154), the official projection of metropolitan France.
+    pub fn lambert93() -> Self {
+        Crs::Projected {
+            epsg: 2154,
+            projection: Projection::LambertConformalConic(LambertConformalConic::new(
+                Ellipsoid::GRS80,
+                3.,
+                46.5,
+                44.,
+                49.,
+                700000.,
+                6600000.,
+            )),
+        }
+    }
+
+    /// RGF93 / CC42 to CC50 (EPSG:3942 to 3950), the conic conformal zones of France,
+    /// each centered on the parallel `zone` (42 to 50).
+    pub fn conic_conformal(zone: u32) -> Option<Self> {
+        if !(42..=50).contains(&zone) {
+            return None;
+        }
+        let lat0 = zone as f64;
+        Some(Crs::Projected {
+            epsg: 3900 + zone,
+            projection: Projection::LambertConformalConic(LambertConformalConic::new(
+                Ellipsoid::GRS80,
+                3.,
+                lat0,
+                lat0 - 0.75,
+                lat0 + 0.75,
+                1700000.,
+                (zone - 41) as f64 * 1000000. + 200000.,
+            )),
+        })
+    }
+
+    /// UTM zone `zone` (1 to 60) of the northern or southern hemisphere: WGS84 / UTM
+    /// (EPSG:326xx and 327xx), or ETRS89 / UTM (EPSG:258xx) with `etrs89`.
+    pub fn utm(zone: u32, north: bool, etrs89: bool) -> Option<Self> {
+        if !(1..=60).contains(&zone) || (etrs89 && !(28..=38).contains(&zone)) {
+            return None;
+        }
+        let (epsg, ellipsoid) = match (etrs89, north) {
+            (true, true) => (25800 + zone, Ellipsoid::GRS80),
+            (true, false) => return None,
+            (false, true) => (32600 + zone, Ellipsoid::WGS84),
+            (false, false) => (32700 + zone, Ellipsoid::WGS84),
+        };
+        Some(Crs::Projected {
+            epsg,
+            projection: Projection::TransverseMercator(TransverseMercator::new(
+                ellipsoid,
+                zone as f64 * 6. - 183.,
+                0.9996,
+                500000.,
+                if north { 0. } else { 10000000. },
+            )),
+        })
+    }
+
+    /// The system of an EPSG code: 4326, 2154, 3942 to 3950, 25828 to 25838, 32601 to 32660
+    /// and 32701 to 32760 are supported.
+    pub fn from_epsg(code: u32) -> Option<Self> {
+        match code {
+            4326 => Some(Crs::Wgs84),
+            2154 => Some(Self::lambert93()),
+            3942..=3950 => Self::conic_conformal(code - 3900),
+            25828..=25838 => Self::utm(code - 25800, true, true),
+            32601..=32660 => Self::utm(code - 32600, true, false),
+            32701..=32760 => Self::utm(code - 32700, false, false),
+            _ => None,
+        }
+    }
+
+    pub fn epsg(&self) -> u32 {
+        match self {
+            Crs::Wgs84 => 4326,
+            Crs::Projected { epsg, .. } => *epsg,
+        }
+    }
+
+    pub fn is_geographic(&self) -> bool {
+        matches!(self, Crs::Wgs84)
+    }
+
+    /// Ellipsoid of the geodetic datum of the system.
+    pub fn ellipsoid(&self) -> Ellipsoid {
+        match self {
+            Crs::Wgs84 => Ellipsoid::WGS84,
+            Crs::Projected { projection, .. } => projection.ellipsoid(),
+        }
+    }
+
+    /// Name of the system, as in the EPSG registry.
+    pub fn name(&self) -> String {
+        let epsg = self.epsg();
+        match epsg {
+            2154 => "RGF93 / Lambert-93".to_string(),
+            3942..=3950 => format!("RGF93 / CC{}", epsg - 3900),
+            25828..=25838 => format!("ETRS89 / UTM zone {}N", epsg - 25800),
+            32601..=32660 => format!("WGS 84 / UTM zone {}N", epsg - 32600),
+            32701..=32760 => format!("WGS 84 / UTM zone {}S", epsg - 32700),
+            _ => "WGS 84".to_string(),
+        }
+    }
+
+    /// Description of the system as OGC WKT 1, as read by GDAL and the CF `crs_wkt` attribute.
+    pub fn wkt(&self) -> String {
+        let ellipsoid = self.ellipsoid();
+        let ((name, epsg), (datum, datum_epsg)) = match self.epsg() {
+            2154 | 3942..=3950 => (("RGF93", 4171), ("Reseau_Geodesique_Francais_1993", 6171)),
+            25828..=25838 => (
+                ("ETRS89", 4258),
+                ("European_Terrestrial_Reference_System_1989", 6258),
+            ),
+            _ => (("WGS 84", 4326), ("WGS_1984", 6326)),
+        };
+        let (spheroid, spheroid_epsg) = if ellipsoid == Ellipsoid::GRS80 {
+            ("GRS 1980", 7019)
+        } else {
+            ("WGS 84", 7030)
+        };
+        let geogcs = format!(
+            "GEOGCS[\"{}\",DATUM[\"{}\",SPHEROID[\"{}\",{},{},AUTHORITY[\"EPSG\",\"{}\"]],\
+             AUTHORITY[\"EPSG\",\"{}\"]],PRIMEM[\"Greenwich\",0,AUTHORITY[\"EPSG\",\"8901\"]],\
+             UNIT[\"degree\",0.0174532925199433,AUTHORITY[\"EPSG\",\"9122\"]],\
+             AUTHORITY[\"EPSG\",\"{}\"]]",
+            name,
+            datum,
+            spheroid,
+            ellipsoid.a,
+            ellipsoid.inverse_flattening(),
+            spheroid_epsg,
+            datum_epsg,
+            epsg
+        );
+        let Crs::Projected { epsg, projection } = self else {
+            return geogcs;
+        };
+        let (method, parameters) = match projection {
+            Projection::LambertConformalConic(lcc) => (
+                "Lambert_Conformal_Conic_2SP",
+                vec![
+                    ("latitude_of_origin", lcc.lat0),
+                    ("central_meridian", lcc.lon0),
+                    ("standard_parallel_1", lcc.lat2),
+                    ("standard_parallel_2", lcc.lat1),
+                    ("false_easting", lcc.false_easting),
+                    ("false_northing", lcc.false_northing),
+                ],
+            ),
+            Projection::TransverseMercator(tm) => (
+                "Transverse_Mercator",
+                vec![
+                    ("latitude_of_origin", 0.),
+                    ("central_meridian", tm.lon0),
+                    ("scale_factor", tm.k0),
+                    ("false_easting", tm.false_easting),
+                    ("false_northing", tm.false_northing),
+                ],
+            ),
+        };
+        let parameters: String = parameters
+            .iter()
+            .map(|(name, value)| format!(",PARAMETER[\"{}\",{}]", name, value))
+            .collect();
+        format!(
+            "PROJCS[\"{}\",{},PROJECTION[\"{}\"]{},UNIT[\"metre\",1,AUTHORITY[\"EPSG\",\"9001\"]],\
+             AXIS[\"Easting\",EAST],AXIS[\"Northing\",NORTH],AUTHORITY[\"EPSG\",\"{}\"]]",
+            self.name(),
+            geogcs,
+            method,
+            parameters,
+            epsg
+        )
+    }
+
+    /// Coordinates in this system of a (longitude, latitude) point.
+    pub fn from_wgs84(&self, lon: f64, lat: f64) -> (f64, f64) {
+        match self {
+            Crs::Wgs84 => (lon, lat),
+            Crs::Projected { projection, .. } => projection.forward(lon, lat),
+        }
+    }
+
+    /// (Longitude, latitude) of a point given in this system.
+    pub fn to_wgs84(&self, x: f64, y: f64) -> (f64, f64) {
+        match self {
+            Crs::Wgs84 => (x, y),
+            Crs::Projected { projection, .. } => projection.inverse(x, y),
+        }
+    }
+}
+
+impl std::fmt::Display for Crs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EPSG:{}", self.epsg())
+    }
+}
+
+impl FromStr for Crs {
+    type Err = anyhow::Error;
+
+    /// Parse an EPSG code, as `EPSG:2154` or `2154`.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let s = s.trim();
+        let code = match s.get(..5) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("EPSG:") => &s[5..],
+            _ => s,
+        };
+        let code: u32 = code
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid CRS '{}', expected EPSG:<code>", s))?;
+        Self::from_epsg(code).ok_or_else(|| anyhow::anyhow!("Unsupported CRS EPSG:{}", code))
+    }
+}
+
+impl Projection {
+    pub fn ellipsoid(&self) -> Ellipsoid {
+        match self {
+            Projection::LambertConformalConic(lcc) => lcc.ellipsoid,
+            Projection::TransverseMercator(tm) => tm.ellipsoid,
+        }
+    }
+
+    pub fn forward(&self, lon: f64, lat: f64) -> (f64, f64) {
+        match self {
+            Projection::LambertConformalConic(lcc) => lcc.forward(lon, lat),
+            Projection::TransverseMercator(tm) => tm.forward(lon, lat),
+        }
+    }
+
+    pub fn inverse(&self, x: f64, y: f64) -> (f64, f64) {
+        match self {
+            Projection::LambertConformalConic(lcc) => lcc.inverse(x, y),
+            Projection::TransverseMercator(tm) => tm.inverse(x, y),
+        }
+    }
+}
+
+/// Lambert conformal conic projection with two standard parallels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LambertConformalConic {
+    pub ellipsoid: Ellipsoid,
+    /// Longitude of origin, in degrees.
+    pub lon0: f64,
+    /// Latitude of origin, in degrees.
+    pub lat0: f64,
+    /// First standard parallel, in degrees.
+    pub lat1: f64,
+    /// Second standard parallel, in degrees.
+    pub lat2: f64,
+    pub false_easting: f64,
+    pub false_northing: f64,
+    // Constants derived from the parameters.
+    e: f64,
+    n: f64,
+    af: f64,
+    rho0: f64,
+}
+
+// Isometric latitude function 't' of the conic projections.
+fn conformal_t(lat: f64, e: f64) -> f64 {
+    let es = e * lat.sin();
+    (FRAC_PI_4 - 0.5 * lat).tan() / ((1. - es) / (1. + es)).powf(0.5 * e)
+}
+
+fn conformal_m(lat: f64, e: f64) -> f64 {
+    lat.cos() / (1. - (e * lat.sin()).powi(2)).sqrt()
+}
+
+impl LambertConformalConic {
+    pub fn new(
+        ellipsoid: Ellipsoid,
+        lon0: f64,
+        lat0: f64,
+        lat1: f64,
+        lat2: f64,
+        false_easting: f64,
+        false_northing: f64,
+    ) -> Self {
+        let e = ellipsoid.eccentricity();
+        let (phi0, phi1, phi2) = (lat0.to_radians(), lat1.to_radians(), lat2.to_radians());
+        let (m1, m2) = (conformal_m(phi1, e), conformal_m(phi2, e));
+        let (t0, t1, t2) = (
+            conformal_t(phi0, e),
+            conformal_t(phi1, e),
+            conformal_t(phi2, e),
+        );
+        let n = (m1.ln() - m2.ln()) / (t1.ln() - t2.ln());
+        let af = ellipsoid.a * m1 / (n * t1.powf(n));
+        Self {
+            ellipsoid,
+            lon0,
+            lat0,
+            lat1,
+            lat2,
+            false_easting,
+            false_northing,
+            e,
+            n,
+            af,
+            rho0: af * t0.powf(n),
+        }
+    }
+
+    pub fn forward(&self, lon: f64, lat: f64) -> (f64, f64) {
+        let rho = self.af * conformal_t(lat.to_radians(), self.e).powf(self.n);
+        let theta = self.n * (lon - self.lon0).to_radians();
+        (
+            self.false_easting + rho * theta.sin(),
+            self.false_northing + self.rho0 - rho * theta.cos(),
+        )
+    }
+
+    pub fn inverse(&self, x: f64, y: f64) -> (f64, f64) {
+        let dx = x - self.false_easting;
+        let dy = self.rho0 - (y - self.false_northing);
+        let rho = self.n.signum() * dx.hypot(dy);
+        let t = (rho / self.af).powf(1. / self.n);
+        let theta = (self.n.signum() * dx).atan2(self.n.signum() * dy);
+
+        // The latitude is the fixed point of phi = pi/2 - 2 atan(t ((1 - e sin phi)/(1 + e sin phi))^(e/2)).
+        let mut lat = FRAC_PI_2 - 2. * t.atan();
+        for _ in 0..15 {
+            let es = self.e * lat.sin();
+            let next = FRAC_PI_2 - 2. * (t * ((1. - es) / (1. + es)).powf(0.5 * self.e)).atan();
+            if (next - lat).abs() < 1e-12 {
+                lat = next;
+                break;
+            }
+            lat = next;
+        }
+        (self.lon0 + (theta / self.n).to_degrees(), lat.to_degrees())
+    }
+}
+
+/// Transverse Mercator projection with its origin on the equator, as used by UTM.
+///
+/// Uses the Krüger series to the third order in the third flattening, accurate to the
+/// millimeter within a UTM zone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransverseMercator {
+    pub ellipsoid: Ellipsoid,
+    /// Central meridian, in degrees.
+    pub lon0: f64,
+    /// Scale factor on the central meridian.
+    pub k0: f64,
+    pub false_easting: f64,
+    pub false_northing: f64,
+    // Constants derived from the ellipsoid.
+    n: f64,
+    big_a: f64,
+    alpha: [f64; 3],
+    beta: [f64; 3],
+    delta: [f64; 3],
+}
+
+impl TransverseMercator {
+    pub fn new(
+        ellipsoid: Ellipsoid,
+        lon0: f64,
+        k0: f64,
+        false_easting: f64,
+        false_northing: f64,
+    ) -> Self {
+        let n = ellipsoid.f / (2. - ellipsoid.f);
+        let (n2, n3) = (n * n, n * n * n);
+        Self {
+            ellipsoid,
+            lon0,
+            k0,
+            false_easting,
+            false_northing,
+            n,
+            big_a: ellipsoid.a / (1. + n) * (1. + n2 / 4. + n2 * n2 / 64.),
+            alpha: [
+                n / 2. - 2. / 3. * n2 + 5. / 16. * n3,
+                13. / 48. * n2 - 3. / 5. * n3,
+                61. / 240. * n3,
+            ],
+            beta: [
+                n / 2. - 2. / 3. * n2 + 37. / 96. * n3,
+                1. / 48. * n2 + 1. / 15. * n3,
+                17. / 480. * n3,
+            ],
+            delta: [
+                2. * n - 2. / 3. * n2 - 2. * n3,
+                7. / 3. * n2 - 8. / 5. * n3,
+                56. / 15. * n3,
+            ],
+        }
+    }
+
+    pub fn forward(&self, lon: f64, lat: f64) -> (f64, f64) {
+        let (phi, lambda) = (lat.to_radians(), (lon - self.lon0).to_radians());
+        let c = 2. * self.n.sqrt() / (1. + self.n);
+        let t = (phi.sin().atanh() - c * (c * phi.sin()).atanh()).sinh();
+        let xi = t.atan2(lambda.cos());
+        let eta = (lambda.sin() / (1. + t * t).sqrt()).atanh();
+
+        let (mut x, mut y) = (eta, xi);
+        for (j, alpha) in self.alpha.iter().enumerate() {
+            let k = 2. * (j + 1) as f64;
+            x += alpha * (k * xi).cos() * (k * eta).sinh();
+            y += alpha * (k * xi).sin() * (k * eta).cosh();
+        }
+        (
+            self.false_easting + self.k0 * self.big_a * x,
+            self.false_northing + self.k0 * self.big_a * y,
+        )
+    }
+
+    pub fn inverse(&self, x: f64, y: f64) -> (f64, f64) {
+        let xi = (y - self.false_northing) / (self.k0 * self.big_a);
+        let eta = (x - self.false_easting) / (self.k0 * self.big_a);
+
+        let (mut xi_p, mut eta_p) = (xi, eta);
+        for (j, beta) in self.beta.iter().enumerate() {
+            let k = 2. * (j + 1) as f64;
+            xi_p -= beta * (k * xi).sin() * (k * eta).cosh();
+            eta_p -= beta * (k * xi).cos() * (k * eta).sinh();
+        }
+        let chi = (xi_p.sin() / eta_p.cosh()).asin();
+        let mut phi = chi;
+        for (j, delta) in self.delta.iter().enumerate() {
+            phi += delta * (2. * (j + 1) as f64 * chi).sin();
+        }
+        let lambda = eta_p.sinh().atan2(xi_p.cos());
+        (self.lon0 + lambda.to_degrees(), phi.to_degrees())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: (f64, f64), expected: (f64, f64), tolerance: f64) {
+        assert!(
+            (actual.0 - expected.0).abs() < tolerance && (actual.1 - expected.1).abs() < tolerance,
+            "{:?} instead of {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn lambert93_origin() {
+        let crs = Crs::from_epsg(2154).unwrap();
+        assert_close(crs.from_wgs84(3., 46.5), (700_000., 6_600_000.), 1e-3);
+    }
+
+    #[test]
+    fn utm_central_meridian() {
+        let crs = Crs::from_epsg(32631).unwrap();
+        assert_close(crs.from_wgs84(3., 45.), (500_000., 4_982_950.40), 0.01);
+    }
+
+    #[test]
+    fn conic_conformal_false_northings() {
+        // Each zone has its origin on the meridian 3°E at the latitude of its number.
+        for zone in 42..=50 {
+            let crs = Crs::from_epsg(3900 + zone).unwrap();
+            let northing = (zone - 41) as f64 * 1_000_000. + 200_000.;
+            assert_close(
+                crs.from_wgs84(3., zone as f64),
+                (1_700_000., northing),
+                1e-3,
+            );
+        }
+        assert!(Crs::conic_conformal(41).is_none());
+        assert!(Crs::conic_conformal(51).is_none());
+    }
+
+    #[test]
+    fn round_trip() {
+        let codes = [2154, 3942, 3950, 25831, 32630, 32632, 32731];
+        let points = [
+            (3., 46.5),
+            (-1.5, 43.4),
+            (7.7, 48.6),
+            (9.4, 42.5),
+            (2.35, 48.85),
+        ];
+        for code in codes {
+            let crs = Crs::from_epsg(code).unwrap();
+            // 1e-8 degree is about a millimeter, the accuracy of the Krüger series.
+            for (lon, lat) in points {
+                let (x, y) = crs.from_wgs84(lon, lat);
+                assert_close(crs.to_wgs84(x, y), (lon, lat), 1e-8);
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use reqwest::blocking::Client;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+
+use crate::retry::{parse_retry_after, Failure, RateLimiter, RetryPolicy, IGN_REQUESTS_PER_SECOND};
+
+/// Altimetry endpoint of the IGN Géoplateforme.
+pub const DEFAULT_ENDPOINT: &str =
+    "https://data.geopf.fr/altimetrie/1.0/calcul/alti/rest/elevation.json";
+/// Elevation dataset used by default: RGE ALTI with a world fallback.
+pub const DEFAULT_RESOURCE: &str = "ign_rge_alti_wld";
+/// Environment variable overriding [`DEFAULT_ENDPOINT`].
+pub const ENDPOINT_ENV: &str = "IGN_ELEVATION_ENDPOINT";
+// Height returned by the service for the points outside the coverage of the dataset.
+const IGN_NO_DATA: f64 = -99999.;
+
+/// Source of elevation data.
+pub trait ElevationProvider {
+    /// Elevation in meters of each (longitude, latitude) point, in the same order as `points`,
+    /// NaN for the points without data.
+    fn elevations(&self, points: &[(f64, f64)]) -> Result<Vec<f64>>;
+}
+
+/// The IGN altimetry REST service.
+///
+/// The no-data value of the service (-99999) is returned as NaN.
+/// Requests go through a connection-pooled client shared by the clones of the provider.
+#[derive(Debug, Clone)]
+pub struct IgnProvider {
+    client: Client,
+    endpoint: String,
+    resource: String,
+    retry: RetryPolicy,
+    rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ElevationResponse {
+    elevations: Vec<f64>,
+}
+
+impl Default for IgnProvider {
+    fn default() -> Self {
+        Self {
+            client: Client::new(),
+            endpoint: std::env::var(ENDPOINT_ENV).unwrap_or_else(|_| DEFAULT_ENDPOINT.to_string()),
+            resource: DEFAULT_RESOURCE.to_string(),
+            retry: RetryPolicy::default(),
+            rate_limiter: Some(Arc::new(RateLimiter::new(IGN_REQUESTS_PER_SECOND))),
+        }
+    }
+}
+
+impl IgnProvider {
+    /// Provider for [`DEFAULT_RESOURCE`] on [`DEFAULT_ENDPOINT`], or on the endpoint set in
+    /// the [`ENDPOINT_ENV`] environment variable, limited to [`IGN_REQUESTS_PER_SECOND`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use another endpoint, e.g. a mirror or a local stand-in of the service.
+    pub fn with_endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = endpoint.to_string();
+        self
+    }
+
+    /// Use another elevation dataset of the service.
+    pub fn with_resource(mut self, resource: &str) -> Self {
+        self.resource = resource.to_string();
+        self
+    }
+
+    /// Retry transient failures according to `retry`.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Send at most `requests_per_second` requests, or remove the limit with `None` or a rate
+    /// that is not positive.
+    /// Clones of the provider share the limit.
+    pub fn with_rate_limit(mut self, requests_per_second: Option<f64>) -> Self {
+        self.rate_limiter = requests_per_second
+            .filter(|&rps| rps > 0.)
+            .map(|rps| Arc::new(RateLimiter::new(rps)));
+        self
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    pub fn resource(&self) -> &str {
+        &self.resource
+    }
+
+    /// Identifier of the data returned, made of the endpoint and the dataset, so that heights
+    /// from a mirror or a stand-in are never mistaken for those of the service.
+    pub fn source(&self) -> String {
+        format!("{} {}", self.endpoint, self.resource)
+    }
+
+    // Full URL of the request for a batch of points.
+    fn url(&self, points: &[(f64, f64)]) -> Result<Url> {
+        let lon_str: Vec<String> = points.iter().map(|p| p.0.to_string()).collect();
+        let lat_str: Vec<String> = points.iter().map(|p| p.1.to_string()).collect();
+        let mut url = Url::parse(&self.endpoint)
+            .with_context(|| format!("Invalid endpoint URL: {}", self.endpoint))?;
+        url.query_pairs_mut()
+            .append_pair("lon", &lon_str.join("|"))
+            .append_pair("lat", &lat_str.join("|"))
+            .append_pair("resource", &self.resource)
+            .append_pair("delimiter", "|")
+            .append_pair("zonly", "true");
+        Ok(url)
+    }
+}
+
+impl ElevationProvider for IgnProvider {
+    fn elevations(&self, points: &[(f64, f64)]) -> Result<Vec<f64>> {
+        let full_url = self.url(points)?;
+        self.retry.retry(|| {
+            if let Some(rate_limiter) = &self.rate_limiter {
+                rate_limiter.wait();
+            }
+            request_elevations(&self.client, full_url.clone())
+        })
+    }
+}
+
+// Send a single request and classify its failure.
+fn request_elevations(client: &Client, full_url: Url) -> Result<Vec<f64>, Failure> {
+    let response = client
+        .get(full_url)
+        .send()
+        .map_err(|err| Failure::Transient {
+            error: anyhow::Error::new(err).context("Failed to get the request"),
+            retry_after: None,
+        })?;
+
+    let status = response.status();
+    if status.is_success() {
+        let elevation_data: ElevationResponse = response
+            .json()
+            .context("Failed to parse response as JSON")
+            .map_err(Failure::Permanent)?;
+        Ok(elevation_data
+            .elevations
+            .into_iter()
+            .map(|h| if h == IGN_NO_DATA { f64::NAN } else { h })
+            .collect())
+    } else {
+        let error = anyhow::anyhow!("Request failed with status: {}", status);
+        if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(parse_retry_after);
+            Err(Failure::Transient { error, retry_after })
+        } else {
+            Err(Failure::Permanent(error))
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::Result;
+
+/// Requests per second allowed by the IGN Géoplateforme altimetry service.
+pub const IGN_REQUESTS_PER_SECOND: f64 = 5.;
+
+/// Outcome of a failed attempt, telling [`RetryPolicy::retry`] whether to try again.
+#[derive(Debug)]
+pub enum Failure {
+    /// Transient failure, e.g. a timeout, a 5xx or a 429 status, optionally with the delay
+    /// requested by the server.
+    Transient {
+        error: anyhow::Error,
+        retry_after: Option<Duration>,
+    },
+    /// Failure that will not go away by trying again.
+    Permanent(anyhow::Error),
+}
+
+/// Retries with exponential backoff and full jitter.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Longest delay requested by the server that is waited for, beyond which it gives up.
+    pub max_retry_after: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            max_retry_after: Duration::from_secs(300),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Random delay before retry number `attempt` (starting at 0), between zero and
+    /// `base_delay * 2^attempt`, capped at `max_delay`.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        ceiling.mul_f64(fastrand::f64())
+    }
+
+    /// Call `f` until it succeeds, fails permanently or runs out of retries. The delays requested
+    /// by the server are honoured, unless longer than `max_retry_after` which fails instead.
+    pub fn retry<T>(&self, mut f: impl FnMut() -> Result<T, Failure>) -> Result<T> {
+        let mut attempt = 0;
+        loop {
+            match f() {
+                Ok(value) => return Ok(value),
+                Err(Failure::Permanent(error)) => return Err(error),
+                Err(Failure::Transient { error, .. }) if attempt >= self.max_retries => {
+                    return Err(error.context(format!("Giving up after {} attempts", attempt + 1)))
+                }
+                Err(Failure::Transient { error, retry_after }) => {
+                    let delay = match retry_after {
+                        Some(delay) if delay > self.max_retry_after => {
+                            return Err(error.context(format!(
+                                "The server asked to retry in {} s, more than the {} s allowed",
+                                delay.as_secs(),
+                                self.max_retry_after.as_secs()
+                            )))
+                        }
+                        Some(delay) => delay,
+                        None => self.backoff(attempt),
+                    };
+                    sleep(delay);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Parse a `Retry-After` header, given either in seconds or as an HTTP date.
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Client-side limit on the number of requests per second, shared between threads.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Limit of `requests_per_second`, no limit if it is not positive.
+    pub fn new(requests_per_second: f64) -> Self {
+        let interval = if requests_per_second > 0. {
+            Duration::from_secs_f64(1. / requests_per_second)
+        } else {
+            Duration::ZERO
+        };
+        Self {
+            interval,
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Block until a request can be sent.
+    pub fn wait(&self) {
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let slot = (*next).max(now);
+            *next = slot + self.interval;
+            slot
+        };
+        sleep(slot.saturating_duration_since(Instant::now()));
+    }
+}
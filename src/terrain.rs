@@ -0,0 +1,234 @@
+//! Products derived from an elevation grid.
+
+use crate::grid::{ElevationGrid, Quantity};
+
+/// Options of [`hillshade`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HillshadeOptions {
+    /// Vertical exaggeration of the heights.
+    pub z_factor: f64,
+    /// Direction of the sun, in degrees clockwise from north.
+    pub azimuth: f64,
+    /// Height of the sun above the horizon, in degrees.
+    pub altitude: f64,
+    /// Combine the lights from the west, north-west, north and south-west, each weighted by
+    /// the aspect of the slopes, instead of a single light from `azimuth`.
+    pub multidirectional: bool,
+}
+
+impl Default for HillshadeOptions {
+    fn default() -> Self {
+        Self {
+            z_factor: 1.,
+            azimuth: 315.,
+            altitude: 45.,
+            multidirectional: false,
+        }
+    }
+}
+
+// Heights of the 3 x 3 cells around a point, from north to south and from west to east, with
+// the size in meters of the cells.
+struct Window {
+    z: [[f64; 3]; 3],
+    dx: f64,
+    dy: f64,
+}
+
+impl Window {
+    // Window around the point (`ix`, `iy`), or `None` without height at the point. The missing
+    // neighbours, beyond the edges of the grid or without data, are extrapolated linearly from
+    // the opposite neighbour, or take the height of the middle cell when both are missing. The
+    // missing corners are extrapolated from the middle row and column.
+    fn new(data: &ElevationGrid, ix: usize, iy: usize) -> Option<Self> {
+        let center = data.get(ix, iy);
+        if center.is_nan() {
+            return None;
+        }
+        let (nx, ny) = data.grid.shape();
+        let mut z = [[f64::NAN; 3]; 3];
+        for (row, cells) in z.iter_mut().enumerate() {
+            for (col, cell) in cells.iter_mut().enumerate() {
+                let (x, y) = ((ix + col).checked_sub(1), (iy + 1).checked_sub(row));
+                if let (Some(x), Some(y)) = (x, y) {
+                    if x < nx && y < ny {
+                        *cell = data.get(x, y);
+                    }
+                }
+            }
+        }
+        let fill = |[a, m, b]: [f64; 3]| match (a.is_nan(), b.is_nan()) {
+            (false, false) => [a, m, b],
+            (true, false) => [2. * m - b, m, b],
+            (false, true) => [a, m, 2. * m - a],
+            (true, true) => [m, m, m],
+        };
+        z[1] = fill(z[1]);
+        [z[0][1], _, z[2][1]] = fill([z[0][1], z[1][1], z[2][1]]);
+        for (row, col) in [(0, 0), (0, 2), (2, 0), (2, 2)] {
+            if z[row][col].is_nan() {
+                z[row][col] = z[row][1] + z[1][col] - z[1][1];
+            }
+        }
+        let (dx, dy) = data.grid.cell_size(iy);
+        Some(Self { z, dx, dy })
+    }
+
+    // Slopes towards the east and the north by Horn's method.
+    fn gradient(&self) -> (f64, f64) {
+        let z = &self.z;
+        (
+            ((z[0][2] + 2. * z[1][2] + z[2][2]) - (z[0][0] + 2. * z[1][0] + z[2][0]))
+                / (8. * self.dx),
+            ((z[0][0] + 2. * z[0][1] + z[0][2]) - (z[2][0] + 2. * z[2][1] + z[2][2]))
+                / (8. * self.dy),
+        )
+    }
+
+    // Profile and plan curvatures of the quadratic surface of Zevenbergen and Thorne (1987),
+    // opposite to the second derivatives of the heights along the slope and along the contour
+    // so that both are positive on convex terrain.
+    fn curvatures(&self) -> (f64, f64) {
+        let (z, dx, dy) = (&self.z, self.dx, self.dy);
+        let zxx = (z[1][0] - 2. * z[1][1] + z[1][2]) / (dx * dx);
+        let zyy = (z[0][1] - 2. * z[1][1] + z[2][1]) / (dy * dy);
+        let zxy = (z[0][2] - z[0][0] - z[2][2] + z[2][0]) / (4. * dx * dy);
+        let (p, q) = (
+            (z[1][2] - z[1][0]) / (2. * dx),
+            (z[0][1] - z[2][1]) / (2. * dy),
+        );
+        let squared = p * p + q * q;
+        if squared == 0. {
+            return (0., 0.);
+        }
+        (
+            -(zxx * p * p + 2. * zxy * p * q + zyy * q * q) / squared,
+            -(zxx * q * q - 2. * zxy * p * q + zyy * p * p) / squared,
+        )
+    }
+}
+
+/// Gradient of the heights at the point (`ix`, `iy`) by Horn's method, as the slopes towards
+/// the east and towards the north in meters per meter, or `None` without height at the point.
+///
+/// The neighbours beyond the edges of the grid or without data are extrapolated from the
+/// opposite ones.
+pub fn horn_gradient(data: &ElevationGrid, ix: usize, iy: usize) -> Option<(f64, f64)> {
+    Window::new(data, ix, iy).map(|window| window.gradient())
+}
+
+/// Shaded relief of `data`, from 0 in full shadow to 255 facing the sun, NaN without height.
+///
+/// The result is laid out on the grid of `data`, like its heights.
+pub fn hillshade(data: &ElevationGrid, options: &HillshadeOptions) -> ElevationGrid {
+    let (nx, ny) = data.grid.shape();
+    let altitude = options.altitude.to_radians();
+    // Illumination of a surface of slopes (p, q) by a light from `azimuth`, the cosine of the
+    // angle between the light and the normal of the surface.
+    let illumination = |p: f64, q: f64, azimuth: f64| {
+        let (sin_azimuth, cos_azimuth) = azimuth.to_radians().sin_cos();
+        (altitude.sin() - (p * sin_azimuth + q * cos_azimuth) * altitude.cos())
+            / (1. + p * p + q * q).sqrt()
+    };
+
+    let mut shade = Vec::with_capacity(nx * ny);
+    for ix in 0..nx {
+        for iy in 0..ny {
+            let Some((p, q)) = horn_gradient(data, ix, iy) else {
+                shade.push(f64::NAN);
+                continue;
+            };
+            let (p, q) = (options.z_factor * p, options.z_factor * q);
+            let value = if options.multidirectional {
+                // Weights summing to 2, largest for the lights across the downslope direction.
+                let aspect = f64::atan2(-p, -q);
+                [225., 270., 315., 360.]
+                    .iter()
+                    .map(|azimuth: &f64| {
+                        (aspect - azimuth.to_radians()).sin().powi(2)
+                            * illumination(p, q, *azimuth).max(0.)
+                    })
+                    .sum::<f64>()
+                    / 2.
+            } else {
+                illumination(p, q, options.azimuth)
+            };
+            shade.push(255. * value.max(0.));
+        }
+    }
+    data.derive(shade, HILLSHADE)
+}
+
+const HILLSHADE: Quantity = Quantity {
+    name: "hillshade",
+    long_name: "shaded relief",
+    units: "1",
+    standard_name: None,
+};
+
+/// Terrain product derived from the heights.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerrainProduct {
+    /// Slope in degrees, from 0 for flat ground to 90.
+    SlopeDegrees,
+    /// Slope in percent, 100 for 45 degrees.
+    SlopePercent,
+    /// Direction of the downslope in degrees clockwise from north, -1 for flat ground.
+    Aspect,
+    /// Curvature along the slope in 1/m, positive on convex slopes which steepen downhill.
+    ProfileCurvature,
+    /// Curvature across the slope in 1/m, positive on the ridges and spurs.
+    PlanCurvature,
+}
+
+impl TerrainProduct {
+    pub fn quantity(&self) -> Quantity {
+        let (name, long_name, units) = match self {
+            TerrainProduct::SlopeDegrees => ("slope", "slope", "degree"),
+            TerrainProduct::SlopePercent => ("slope_percent", "slope in percent", "percent"),
+            TerrainProduct::Aspect => ("aspect", "downslope direction from north", "degree"),
+            TerrainProduct::ProfileCurvature => ("profile_curvature", "profile curvature", "m-1"),
+            TerrainProduct::PlanCurvature => ("plan_curvature", "plan curvature", "m-1"),
+        };
+        Quantity {
+            name,
+            long_name,
+            units,
+            standard_name: None,
+        }
+    }
+
+    // Value of the product at a point.
+    fn value(&self, window: &Window) -> f64 {
+        match self {
+            TerrainProduct::SlopeDegrees => {
+                let (p, q) = window.gradient();
+                p.hypot(q).atan().to_degrees()
+            }
+            TerrainProduct::SlopePercent => {
+                let (p, q) = window.gradient();
+                100. * p.hypot(q)
+            }
+            TerrainProduct::Aspect => match window.gradient() {
+                (p, q) if p == 0. && q == 0. => -1.,
+                (p, q) => f64::atan2(-p, -q).to_degrees().rem_euclid(360.),
+            },
+            TerrainProduct::ProfileCurvature => window.curvatures().0,
+            TerrainProduct::PlanCurvature => window.curvatures().1,
+        }
+    }
+}
+
+/// A terrain product of `data` computed on the real size in meters of its cells, NaN without
+/// height. The result is laid out on the grid of `data`, like its heights.
+pub fn terrain_product(data: &ElevationGrid, product: TerrainProduct) -> ElevationGrid {
+    let (nx, ny) = data.grid.shape();
+    let values = (0..nx)
+        .flat_map(|ix| (0..ny).map(move |iy| (ix, iy)))
+        .map(|(ix, iy)| match Window::new(data, ix, iy) {
+            Some(window) => product.value(&window),
+            None => f64::NAN,
+        })
+        .collect();
+    data.derive(values, product.quantity())
+}
@@ -0,0 +1,190 @@
+//! Visibility of the terrain from observer points.
+
+use anyhow::{Context, Result};
+
+use crate::grid::{ElevationGrid, LatLon, Quantity, EARTH_RADIUS};
+
+/// Usual coefficient of the atmospheric refraction, bending the lines of sight towards the
+/// ground.
+pub const DEFAULT_REFRACTION: f64 = 0.13;
+
+/// Options of [`viewshed`] and [`visibility_count`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewshedOptions {
+    /// Height of the eyes or antenna of the observer above the ground, in meters.
+    pub observer_height: f64,
+    /// Height above the ground of the points to see, in meters.
+    pub target_height: f64,
+    /// Distance beyond which nothing is visible, in meters.
+    pub max_distance: Option<f64>,
+    /// Lower the distant points by the curvature of the earth.
+    pub curvature: bool,
+    /// Coefficient of refraction, reducing the curvature correction.
+    pub refraction: f64,
+}
+
+impl Default for ViewshedOptions {
+    fn default() -> Self {
+        Self {
+            observer_height: 1.7,
+            target_height: 0.,
+            max_distance: None,
+            curvature: true,
+            refraction: DEFAULT_REFRACTION,
+        }
+    }
+}
+
+const VISIBILITY: Quantity = Quantity {
+    name: "visibility",
+    long_name: "visible from an observer",
+    units: "1",
+    standard_name: None,
+};
+
+const VISIBILITY_COUNT: Quantity = Quantity {
+    name: "visibility_count",
+    long_name: "number of observers in view",
+    units: "1",
+    standard_name: None,
+};
+
+/// Visibility mask of `data`: 1 for the points visible from at least one of the `observers`, 0
+/// for the hidden ones and the ones beyond the maximum distance, NaN without height.
+pub fn viewshed(
+    data: &ElevationGrid,
+    observers: &[LatLon],
+    options: &ViewshedOptions,
+) -> Result<ElevationGrid> {
+    let counts = counts(data, observers, options)?;
+    Ok(data.derive(counts.into_iter().map(|c| c.min(1.)).collect(), VISIBILITY))
+}
+
+/// Cumulative visibility of `data`: the number of `observers` from which each point is visible,
+/// NaN without height.
+pub fn visibility_count(
+    data: &ElevationGrid,
+    observers: &[LatLon],
+    options: &ViewshedOptions,
+) -> Result<ElevationGrid> {
+    Ok(data.derive(counts(data, observers, options)?, VISIBILITY_COUNT))
+}
+
+// Number of observers seeing each point of the grid.
+fn counts(
+    data: &ElevationGrid,
+    observers: &[LatLon],
+    options: &ViewshedOptions,
+) -> Result<Vec<f64>> {
+    let mut counts: Vec<f64> = data
+        .heights
+        .iter()
+        .map(|h| if h.is_nan() { f64::NAN } else { 0. })
+        .collect();
+    for observer in observers {
+        let visible = line_of_sight(data, observer, options)?;
+        for (count, visible) in counts.iter_mut().zip(visible) {
+            if visible {
+                *count += 1.;
+            }
+        }
+    }
+    Ok(counts)
+}
+
+// Points of the grid visible from `observer`, by the R2 algorithm of Franklin and Ray (1994):
+// rays are cast from the observer to every cell on the edges of the area within reach, and each
+// cell along a ray is visible if it rises above all the terrain before it on the ray.
+fn line_of_sight(
+    data: &ElevationGrid,
+    observer: &LatLon,
+    options: &ViewshedOptions,
+) -> Result<Vec<bool>> {
+    let grid = &data.grid;
+    let (nx, ny) = grid.shape();
+    let (ox, oy) = grid
+        .nearest_point(observer)
+        .with_context(|| format!("The observer {} is outside the map", observer))?;
+    let ground = data.get(ox, oy);
+    anyhow::ensure!(!ground.is_nan(), "No height at the observer {}", observer);
+    let eye = ground + options.observer_height;
+    // The cells are taken with their size at the observer, the area being small.
+    let (cell_x, cell_y) = grid.cell_size(oy);
+    let drop_factor = if options.curvature {
+        (1. - options.refraction) / (2. * EARTH_RADIUS)
+    } else {
+        0.
+    };
+    let max_distance = options.max_distance.unwrap_or(f64::INFINITY);
+
+    // Area within reach of the observer, in cells.
+    let reach = |cells: usize, size: f64| (max_distance / size).ceil().min(cells as f64) as usize;
+    let (x_min, x_max) = (
+        ox.saturating_sub(reach(nx, cell_x)),
+        (ox + reach(nx, cell_x)).min(nx - 1),
+    );
+    let (y_min, y_max) = (
+        oy.saturating_sub(reach(ny, cell_y)),
+        (oy + reach(ny, cell_y)).min(ny - 1),
+    );
+    let mut targets = Vec::new();
+    for ix in x_min..=x_max {
+        targets.extend([(ix, y_min), (ix, y_max)]);
+    }
+    for iy in y_min..=y_max {
+        targets.extend([(x_min, iy), (x_max, iy)]);
+    }
+
+    let mut visible = vec![false; nx * ny];
+    visible[ox * ny + oy] = true;
+    for (tx, ty) in targets {
+        let (run_x, run_y) = (tx as f64 - ox as f64, ty as f64 - oy as f64);
+        let steps = run_x.abs().max(run_y.abs()) as usize;
+        let mut horizon = f64::NEG_INFINITY;
+        for step in 1..=steps {
+            // The coordinate along the major axis of the ray is a whole number of cells.
+            let t = step as f64 / steps as f64;
+            let (fx, fy) = if run_x.abs() >= run_y.abs() {
+                (
+                    ox as f64 + step as f64 * run_x.signum(),
+                    oy as f64 + t * run_y,
+                )
+            } else {
+                (
+                    ox as f64 + t * run_x,
+                    oy as f64 + step as f64 * run_y.signum(),
+                )
+            };
+            let distance = (t * run_x * cell_x).hypot(t * run_y * cell_y);
+            if distance > max_distance {
+                break;
+            }
+            let height = interpolate(data, fx, fy);
+            if height.is_nan() {
+                continue;
+            }
+            let base = eye + drop_factor * distance * distance;
+            let (ix, iy) = (fx.round() as usize, fy.round() as usize);
+            if (height + options.target_height - base) / distance >= horizon {
+                visible[ix * ny + iy] = true;
+            }
+            horizon = horizon.max((height - base) / distance);
+        }
+    }
+    Ok(visible)
+}
+
+// Height at a point between two neighbours along x or along y, one coordinate being a whole
+// number of cells.
+fn interpolate(data: &ElevationGrid, fx: f64, fy: f64) -> f64 {
+    let (x0, y0) = (fx.floor(), fy.floor());
+    let (tx, ty) = (fx - x0, fy - y0);
+    let (x0, y0) = (x0 as usize, y0 as usize);
+    if tx > 0. {
+        (1. - tx) * data.get(x0, y0) + tx * data.get(x0 + 1, y0)
+    } else if ty > 0. {
+        (1. - ty) * data.get(x0, y0) + ty * data.get(x0, y0 + 1)
+    } else {
+        data.get(x0, y0)
+    }
+}